@@ -0,0 +1,473 @@
+// talks to the local adb server directly over its smart-socket protocol instead of
+// starting an adb process per command. requests are "<4 hex digit length><payload>",
+// answered with OKAY or FAIL plus a length-prefixed message
+
+use crate::backend::{parse_devices, DeviceBackend, DeviceEntry};
+use crate::error::AdbError;
+use crate::session::{ShellSession, ShellStream, COMMAND_TIMEOUT};
+use std::env;
+use std::fs;
+use std::io::{BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+const DEFAULT_PORT: u16 = 5037;
+// appended to every shell command so its exit code can be told apart from the output
+const EXIT_MARKER: &str = "__adb_exit__:";
+// sync protocol chunks are at most this big
+const SYNC_DATA_MAX: usize = 64 * 1024;
+
+pub struct AdbClient {
+    addr: String,
+    // start the server with `adb start-server` when it is not running
+    start_server: bool,
+}
+
+impl AdbClient {
+    pub fn new(addr: &str) -> Self {
+        AdbClient {
+            addr: addr.to_string(),
+            start_server: false,
+        }
+    }
+
+    // 127.0.0.1 on $ANDROID_ADB_SERVER_PORT or 5037, like the adb binary
+    pub fn from_env() -> Self {
+        let port = env::var("ANDROID_ADB_SERVER_PORT").ok()
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(DEFAULT_PORT);
+        AdbClient {
+            start_server: true,
+            ..AdbClient::new(&format!("127.0.0.1:{}", port))
+        }
+    }
+
+    fn open(&self) -> Result<TcpStream, AdbError> {
+        let stream = match TcpStream::connect(&self.addr) {
+            Ok(stream) => stream,
+            Err(_) if self.start_server => {
+                // what the adb binary does on first use
+                Command::new("adb").arg("start-server")
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status()
+                    .map_err(|e| AdbError::AdbMissing(format!("failed to start the adb server: {}", e)))?;
+                TcpStream::connect(&self.addr)
+                    .map_err(|e| AdbError::AdbMissing(format!("cannot connect to the adb server at {}: {}", self.addr, e)))?
+            }
+            Err(e) => return Err(AdbError::AdbMissing(format!("cannot connect to the adb server at {}: {}", self.addr, e))),
+        };
+        stream.set_read_timeout(Some(COMMAND_TIMEOUT)).ok();
+        Ok(stream)
+    }
+
+    // a host service that answers with one length-prefixed string
+    fn query(&self, service: &str) -> Result<String, AdbError> {
+        let mut stream = self.open()?;
+        send_request(&mut stream, service)?;
+        read_hex_string(&mut stream)
+    }
+
+    // a connection switched to the device, ready for a device service
+    fn transport(&self, serial: Option<&str>) -> Result<TcpStream, AdbError> {
+        let mut stream = self.open()?;
+        match serial {
+            Some(serial) => send_request(&mut stream, &format!("host:transport:{}", serial))?,
+            None => send_request(&mut stream, "host:transport-any")?,
+        }
+        Ok(stream)
+    }
+
+    // runs a device service and returns everything it writes until it closes
+    fn run_service(&self, serial: Option<&str>, service: &str) -> Result<String, AdbError> {
+        let mut stream = self.transport(serial)?;
+        send_request(&mut stream, service)?;
+        read_all(&mut stream)
+    }
+
+    fn exec(&self, serial: Option<&str>, command: &str, input: Option<&[u8]>) -> Result<String, AdbError> {
+        let mut stream = self.transport(serial)?;
+        send_request(&mut stream, &format!("exec:{}", command))?;
+        if let Some(input) = input {
+            stream.write_all(input).map_err(io_error)?;
+            // only the write side, the answer still has to come back
+            stream.shutdown(std::net::Shutdown::Write).ok();
+        }
+        read_all(&mut stream)
+    }
+}
+
+impl DeviceBackend for AdbClient {
+    fn devices(&self) -> Result<Vec<DeviceEntry>, AdbError> {
+        Ok(parse_devices(&self.query("host:devices-l")?))
+    }
+
+    fn shell(&self, serial: Option<&str>, args: &[&str]) -> Result<String, AdbError> {
+        // joined with spaces like `adb shell a b c` does
+        let command = format!("{} ; echo {}$?", args.join(" "), EXIT_MARKER);
+        let output = self.run_service(serial, &format!("shell:{}", command))?;
+        let (output, code) = split_exit_code(&output);
+        match code {
+            Some(0) => Ok(output),
+            _ => Err(AdbError::from_output(&output, code)),
+        }
+    }
+
+    fn pair(&self, address: &str, code: &str) -> Result<String, AdbError> {
+        let output = self.query(&format!("host:pair:{}:{}", code, address))?;
+        if output.contains("Successfully paired") {
+            Ok(output)
+        } else {
+            Err(AdbError::Other(output))
+        }
+    }
+
+    fn connect(&self, address: &str) -> Result<String, AdbError> {
+        let output = self.query(&format!("host:connect:{}", address))?;
+        if output.contains("connected to") {
+            Ok(output)
+        } else {
+            Err(AdbError::Other(output))
+        }
+    }
+
+    fn disconnect(&self, address: &str) -> Result<String, AdbError> {
+        self.query(&format!("host:disconnect:{}", address))
+    }
+
+    fn tcpip(&self, serial: Option<&str>, port: u16) -> Result<String, AdbError> {
+        self.run_service(serial, &format!("tcpip:{}", port))
+    }
+
+    fn pull(&self, serial: Option<&str>, remote: &str, local: &Path) -> Result<String, AdbError> {
+        let mut stream = self.transport(serial)?;
+        send_request(&mut stream, "sync:")?;
+        send_sync(&mut stream, b"RECV", remote.as_bytes())?;
+
+        let mut content = Vec::new();
+        loop {
+            let (id, len) = read_sync_header(&mut stream)?;
+            match &id {
+                b"DATA" => {
+                    if len > SYNC_DATA_MAX {
+                        return Err(AdbError::Other(format!("adb: sync chunk too big ({} bytes)", len)));
+                    }
+                    let start = content.len();
+                    content.resize(start + len, 0);
+                    stream.read_exact(&mut content[start..]).map_err(io_error)?;
+                }
+                b"DONE" => break,
+                b"FAIL" => {
+                    let mut message = vec![0; len];
+                    stream.read_exact(&mut message).map_err(io_error)?;
+                    return Err(AdbError::Other(format!("adb: error: {}", String::from_utf8_lossy(&message))));
+                }
+                other => return Err(AdbError::Other(format!("adb: unexpected sync reply {}", String::from_utf8_lossy(other)))),
+            }
+        }
+        send_sync(&mut stream, b"QUIT", b"").ok();
+
+        fs::write(local, &content).map_err(|e| AdbError::Other(format!("{}: {}", local.display(), e)))?;
+        Ok(format!("{}: 1 file pulled, {} bytes\n", remote, content.len()))
+    }
+
+    // what `adb install-multiple` does: one package manager session, each apk written into it
+    fn install_multiple(&self, serial: Option<&str>, apks: &[&Path], user: u32) -> Result<String, AdbError> {
+        let mut files = Vec::new();
+        for apk in apks {
+            files.push(fs::read(apk).map_err(|e| AdbError::Other(format!("{}: {}", apk.display(), e)))?);
+        }
+        let total: usize = files.iter().map(Vec::len).sum();
+
+        let created = self.exec(serial, &format!("cmd package install-create -r --user {} -S {}", user, total), None)?;
+        let session = parse_session_id(&created).ok_or_else(|| AdbError::from_output(&created, None))?;
+
+        let result = apks.iter().zip(&files).enumerate().try_for_each(|(i, (apk, content))| {
+            let name = apk.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            let command = format!("cmd package install-write -S {} {} {}_{} -", content.len(), session, i, name);
+            let output = self.exec(serial, &command, Some(content))?;
+            if output.contains("Success") { Ok(()) } else { Err(AdbError::from_output(&output, None)) }
+        });
+        if let Err(e) = result {
+            self.exec(serial, &format!("cmd package install-abandon {}", session), None).ok();
+            return Err(e);
+        }
+
+        let output = self.exec(serial, &format!("cmd package install-commit {}", session), None)?;
+        if output.contains("Success") {
+            Ok(output)
+        } else {
+            Err(AdbError::from_output(&output, None))
+        }
+    }
+
+    fn open_session(&self, serial: Option<&str>) -> Result<Box<dyn ShellSession>, AdbError> {
+        let mut stream = self.transport(serial)?;
+        send_request(&mut stream, "exec:sh")?;
+        // open()'s timeout stays, it only runs while waiting on a command, so a hung pm
+        // fails the session instead of blocking forever. idle time between commands is not counted
+        let reader = BufReader::new(stream.try_clone().map_err(io_error)?);
+        Ok(Box::new(SocketShell {
+            stream: ShellStream::new(reader, stream),
+        }))
+    }
+}
+
+// `exec:sh` on the device: raw pipes, no terminal to echo the commands back
+struct SocketShell {
+    stream: ShellStream<BufReader<TcpStream>, TcpStream>,
+}
+
+impl ShellSession for SocketShell {
+    fn run(&mut self, args: &[&str]) -> std::io::Result<(String, i32)> {
+        self.stream.run(args)
+    }
+}
+
+impl Drop for SocketShell {
+    fn drop(&mut self) {
+        self.stream.close();
+    }
+}
+
+fn io_error(e: std::io::Error) -> AdbError {
+    AdbError::Other(format!("adb: connection to the server failed: {}", e))
+}
+
+fn send_request(stream: &mut TcpStream, payload: &str) -> Result<(), AdbError> {
+    stream.write_all(format!("{:04x}{}", payload.len(), payload).as_bytes()).map_err(io_error)?;
+    read_status(stream)
+}
+
+fn read_status(stream: &mut TcpStream) -> Result<(), AdbError> {
+    let mut status = [0; 4];
+    stream.read_exact(&mut status).map_err(io_error)?;
+    match &status {
+        b"OKAY" => Ok(()),
+        b"FAIL" => Err(AdbError::from_output(&read_hex_string(stream)?, None)),
+        other => Err(AdbError::Other(format!("adb: unexpected reply {}", String::from_utf8_lossy(other)))),
+    }
+}
+
+fn read_hex_string(stream: &mut TcpStream) -> Result<String, AdbError> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len).map_err(io_error)?;
+    let len = std::str::from_utf8(&len).ok()
+        .and_then(|l| usize::from_str_radix(l, 16).ok())
+        .ok_or_else(|| AdbError::Other("adb: bad length in reply".to_string()))?;
+
+    let mut message = vec![0; len];
+    stream.read_exact(&mut message).map_err(io_error)?;
+    Ok(String::from_utf8_lossy(&message).to_string())
+}
+
+fn read_all(stream: &mut TcpStream) -> Result<String, AdbError> {
+    let mut output = Vec::new();
+    stream.read_to_end(&mut output).map_err(io_error)?;
+    Ok(String::from_utf8_lossy(&output).to_string())
+}
+
+fn send_sync(stream: &mut TcpStream, id: &[u8; 4], data: &[u8]) -> Result<(), AdbError> {
+    let mut packet = id.to_vec();
+    packet.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    packet.extend_from_slice(data);
+    stream.write_all(&packet).map_err(io_error)
+}
+
+fn read_sync_header(stream: &mut TcpStream) -> Result<([u8; 4], usize), AdbError> {
+    let mut header = [0; 8];
+    stream.read_exact(&mut header).map_err(io_error)?;
+    let id = [header[0], header[1], header[2], header[3]];
+    let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    Ok((id, len))
+}
+
+// the command's output and the exit code echoed after it
+fn split_exit_code(output: &str) -> (String, Option<i32>) {
+    match output.rfind(EXIT_MARKER) {
+        Some(at) => {
+            let code = output[at + EXIT_MARKER.len()..].trim().parse().ok();
+            (output[..at].to_string(), code)
+        }
+        None => (output.to_string(), None),
+    }
+}
+
+// "Success: created install session [1234567]"
+fn parse_session_id(output: &str) -> Option<String> {
+    let start = output.find('[')? + 1;
+    let end = start + output[start..].find(']')?;
+    Some(output[start..end].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::net::TcpListener;
+    use std::thread;
+
+    type Handler = Box<dyn FnOnce(&mut TcpStream) + Send>;
+
+    // a fake adb server, each handler scripts one connection in order
+    fn fake_server(handlers: Vec<Handler>) -> (AdbClient, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            for handler in handlers {
+                let (mut stream, _) = listener.accept().unwrap();
+                handler(&mut stream);
+            }
+        });
+        (AdbClient::new(&addr), server)
+    }
+
+    fn expect_request(stream: &mut TcpStream, expected: &str) {
+        let mut len = [0; 4];
+        stream.read_exact(&mut len).unwrap();
+        let len = usize::from_str_radix(std::str::from_utf8(&len).unwrap(), 16).unwrap();
+        let mut payload = vec![0; len];
+        stream.read_exact(&mut payload).unwrap();
+        assert_eq!(String::from_utf8(payload).unwrap(), expected);
+    }
+
+    fn okay_with(stream: &mut TcpStream, message: &str) {
+        stream.write_all(format!("OKAY{:04x}{}", message.len(), message).as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn lists_devices_and_reports_failures() {
+        let (client, server) = fake_server(vec![
+            Box::new(|s| {
+                expect_request(s, "host:devices-l");
+                okay_with(s, "R58M123ABC             device usb:1-1 model:SM_G973F transport_id:1\n");
+            }),
+            Box::new(|s| {
+                expect_request(s, "host:connect:192.168.1.20:5555");
+                okay_with(s, "failed to connect to 192.168.1.20:5555");
+            }),
+            Box::new(|s| {
+                expect_request(s, "host:transport:gone");
+                s.write_all(b"FAIL0017device 'gone' not found").unwrap();
+            }),
+        ]);
+
+        let devices = client.devices().unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].serial, "R58M123ABC");
+        assert!(client.connect("192.168.1.20:5555").is_err());
+        assert_eq!(client.shell(Some("gone"), &["pm", "list", "users"]).unwrap_err(), AdbError::DeviceNotFound("gone".to_string()));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn shell_output_and_exit_codes() {
+        let (client, server) = fake_server(vec![
+            Box::new(|s| {
+                expect_request(s, "host:transport:R58M123ABC");
+                s.write_all(b"OKAY").unwrap();
+                expect_request(s, "shell:pm list packages -s ; echo __adb_exit__:$?");
+                s.write_all(b"OKAY").unwrap();
+                s.write_all(b"package:com.android.egg\n__adb_exit__:0\n").unwrap();
+            }),
+            Box::new(|s| {
+                expect_request(s, "host:transport-any");
+                s.write_all(b"OKAY").unwrap();
+                expect_request(s, "shell:pm uninstall --user 0 com.android.systemui ; echo __adb_exit__:$?");
+                s.write_all(b"OKAY").unwrap();
+                s.write_all(b"Failure [DELETE_FAILED_INTERNAL_ERROR]\n__adb_exit__:1\n").unwrap();
+            }),
+        ]);
+
+        assert_eq!(client.shell(Some("R58M123ABC"), &["pm", "list", "packages", "-s"]).unwrap(), "package:com.android.egg\n");
+        let error = client.shell(None, &["pm", "uninstall", "--user", "0", "com.android.systemui"]).unwrap_err();
+        assert_eq!(error, AdbError::Package("DELETE_FAILED_INTERNAL_ERROR".to_string()));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn pulls_files_over_sync() {
+        let content: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let sent = content.clone();
+        let (client, server) = fake_server(vec![
+            Box::new(move |s| {
+                expect_request(s, "host:transport:R58M123ABC");
+                s.write_all(b"OKAY").unwrap();
+                expect_request(s, "sync:");
+                s.write_all(b"OKAY").unwrap();
+
+                let mut header = [0; 8];
+                s.read_exact(&mut header).unwrap();
+                assert_eq!(&header[..4], b"RECV");
+                let mut path = vec![0; u32::from_le_bytes(header[4..].try_into().unwrap()) as usize];
+                s.read_exact(&mut path).unwrap();
+                assert_eq!(path, b"/system/app/Egg/Egg.apk");
+
+                for chunk in sent.chunks(SYNC_DATA_MAX) {
+                    s.write_all(b"DATA").unwrap();
+                    s.write_all(&(chunk.len() as u32).to_le_bytes()).unwrap();
+                    s.write_all(chunk).unwrap();
+                }
+                s.write_all(b"DONE\0\0\0\0").unwrap();
+            }),
+            Box::new(|s| {
+                expect_request(s, "host:transport-any");
+                s.write_all(b"OKAY").unwrap();
+                expect_request(s, "sync:");
+                s.write_all(b"OKAY").unwrap();
+                let mut header = [0; 8];
+                s.read_exact(&mut header).unwrap();
+                let mut path = vec![0; u32::from_le_bytes(header[4..].try_into().unwrap()) as usize];
+                s.read_exact(&mut path).unwrap();
+                let message = b"No such file or directory";
+                s.write_all(b"FAIL").unwrap();
+                s.write_all(&(message.len() as u32).to_le_bytes()).unwrap();
+                s.write_all(message).unwrap();
+            }),
+        ]);
+
+        let local = env::temp_dir().join(format!("android_debloater_test_{}_sync.apk", std::process::id()));
+        client.pull(Some("R58M123ABC"), "/system/app/Egg/Egg.apk", &local).unwrap();
+        assert_eq!(fs::read(&local).unwrap(), content);
+        assert!(client.pull(None, "/nope.apk", &local).unwrap_err().to_string().contains("No such file"));
+        let _ = fs::remove_file(&local);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn session_runs_commands_on_one_connection() {
+        let (client, server) = fake_server(vec![
+            Box::new(|s| {
+                expect_request(s, "host:transport:R58M123ABC");
+                s.write_all(b"OKAY").unwrap();
+                expect_request(s, "exec:sh");
+                s.write_all(b"OKAY").unwrap();
+
+                let mut lines = BufReader::new(s.try_clone().unwrap()).lines();
+                let marker = format!("__android_debloater_{}_", std::process::id());
+                for (n, (reply, code)) in [("Success\n", 0), ("Failure [not installed for 0]\n", 1)].iter().enumerate() {
+                    let command = lines.next().unwrap().unwrap();
+                    assert!(command.starts_with("pm uninstall --user 0 com.facebook.katana 2>&1"));
+                    s.write_all(format!("{}{}{}:{}\n", reply, marker, n + 1, code).as_bytes()).unwrap();
+                }
+                assert_eq!(lines.next().unwrap().unwrap(), "exit");
+            }),
+        ]);
+
+        let mut session = client.open_session(Some("R58M123ABC")).unwrap();
+        let uninstall = ["pm", "uninstall", "--user", "0", "com.facebook.katana"];
+        assert_eq!(session.run(&uninstall).unwrap(), ("Success\n".to_string(), 0));
+        assert_eq!(session.run(&uninstall).unwrap().1, 1);
+        drop(session);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn parses_exit_codes_and_sessions() {
+        assert_eq!(split_exit_code("ok\n__adb_exit__:0\n"), ("ok\n".to_string(), Some(0)));
+        assert_eq!(split_exit_code("cut off"), ("cut off".to_string(), None));
+        assert_eq!(parse_session_id("Success: created install session [1234567]\n"), Some("1234567".to_string()));
+        assert_eq!(parse_session_id("Failure"), None);
+    }
+}
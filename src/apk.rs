@@ -0,0 +1,606 @@
+// just enough apk reading to get an app's label: the zip directory, the binary
+// AndroidManifest.xml and string values from resources.arsc
+
+use miniz_oxide::inflate::decompress_to_vec_with_limit;
+
+// android:label
+const LABEL_ATTR: u32 = 0x0101_0001;
+
+const RES_STRING_POOL: u16 = 0x0001;
+const RES_TABLE: u16 = 0x0002;
+const RES_XML: u16 = 0x0003;
+const RES_XML_START_ELEMENT: u16 = 0x0102;
+const RES_XML_RESOURCE_MAP: u16 = 0x0180;
+const RES_TABLE_PACKAGE: u16 = 0x0200;
+const RES_TABLE_TYPE: u16 = 0x0201;
+
+const TYPE_REFERENCE: u8 = 0x01;
+const TYPE_STRING: u8 = 0x03;
+
+// manifests and resource tables are small, anything bigger is not what we want
+const MAX_ENTRY_SIZE: usize = 64 * 1024 * 1024;
+
+// the application label, None when the apk has none we can read
+pub fn read_label(apk: &[u8]) -> Result<Option<String>, String> {
+    let manifest = zip_entry(apk, "AndroidManifest.xml")?
+        .ok_or("no AndroidManifest.xml in apk")?;
+
+    let (data_type, data, raw) = match manifest_label(&manifest)? {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    match data_type {
+        TYPE_STRING => Ok(raw),
+        TYPE_REFERENCE => {
+            let table = match zip_entry(apk, "resources.arsc")? {
+                Some(table) => table,
+                None => return Ok(None),
+            };
+            resolve_string(&table, data)
+        }
+        _ => Ok(None),
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, String> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| "truncated data".to_string())
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, String> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| "truncated data".to_string())
+}
+
+fn u8_at(data: &[u8], offset: usize) -> Result<u8, String> {
+    data.get(offset).copied().ok_or_else(|| "truncated data".to_string())
+}
+
+// reads one file out of the zip through its central directory
+fn zip_entry(zip: &[u8], name: &str) -> Result<Option<Vec<u8>>, String> {
+    // end of central directory record, followed by a comment of up to 64k
+    let search_start = zip.len().saturating_sub(22 + 0xffff);
+    let eocd = (search_start..zip.len().saturating_sub(21))
+        .rev()
+        .find(|&i| zip[i..i + 4] == [0x50, 0x4b, 0x05, 0x06])
+        .ok_or("not a zip file")?;
+
+    let count = u16_at(zip, eocd + 10)? as usize;
+    let mut offset = u32_at(zip, eocd + 16)? as usize;
+
+    for _ in 0..count {
+        if u32_at(zip, offset)? != 0x0201_4b50 {
+            return Err("corrupt zip directory".to_string());
+        }
+
+        let method = u16_at(zip, offset + 10)?;
+        let compressed_size = u32_at(zip, offset + 20)? as usize;
+        let name_len = u16_at(zip, offset + 28)? as usize;
+        let extra_len = u16_at(zip, offset + 30)? as usize;
+        let comment_len = u16_at(zip, offset + 32)? as usize;
+        let local_offset = u32_at(zip, offset + 42)? as usize;
+        let entry_name = zip.get(offset + 46..offset + 46 + name_len).ok_or("truncated data")?;
+
+        if entry_name == name.as_bytes() {
+            let local_name_len = u16_at(zip, local_offset + 26)? as usize;
+            let local_extra_len = u16_at(zip, local_offset + 28)? as usize;
+            let start = local_offset + 30 + local_name_len + local_extra_len;
+            let data = zip.get(start..start + compressed_size).ok_or("truncated data")?;
+
+            return match method {
+                0 => Ok(Some(data.to_vec())),
+                8 => decompress_to_vec_with_limit(data, MAX_ENTRY_SIZE)
+                    .map(Some)
+                    .map_err(|e| format!("{}: {:?}", name, e.status)),
+                other => Err(format!("{}: unsupported compression method {}", name, other)),
+            };
+        }
+
+        offset += 46 + name_len + extra_len + comment_len;
+    }
+
+    Ok(None)
+}
+
+// the strings of a ResStringPool chunk starting at `offset`
+fn string_pool(data: &[u8], offset: usize) -> Result<Vec<String>, String> {
+    let header_size = u16_at(data, offset + 2)? as usize;
+    let count = u32_at(data, offset + 8)? as usize;
+    let utf8 = u32_at(data, offset + 16)? & (1 << 8) != 0;
+    let strings_start = offset + u32_at(data, offset + 20)? as usize;
+
+    // the count comes from the file, each string needs a 4 byte offset so it cannot be more
+    let mut strings = Vec::with_capacity(count.min(data.len().saturating_sub(offset) / 4));
+    for i in 0..count {
+        let at = strings_start + u32_at(data, offset + header_size + i * 4)? as usize;
+        strings.push(if utf8 { utf8_string(data, at)? } else { utf16_string(data, at)? });
+    }
+    Ok(strings)
+}
+
+fn utf8_string(data: &[u8], at: usize) -> Result<String, String> {
+    // utf-16 length then byte length, each one or two bytes
+    let skip_len = |at: usize| -> Result<(usize, usize), String> {
+        let first = u8_at(data, at)? as usize;
+        if first & 0x80 != 0 {
+            Ok((((first & 0x7f) << 8) | u8_at(data, at + 1)? as usize, at + 2))
+        } else {
+            Ok((first, at + 1))
+        }
+    };
+
+    let (_, at) = skip_len(at)?;
+    let (len, at) = skip_len(at)?;
+    let bytes = data.get(at..at + len).ok_or("truncated data")?;
+    Ok(String::from_utf8_lossy(bytes).to_string())
+}
+
+fn utf16_string(data: &[u8], at: usize) -> Result<String, String> {
+    let first = u16_at(data, at)? as usize;
+    let (len, at) = if first & 0x8000 != 0 {
+        (((first & 0x7fff) << 16) | u16_at(data, at + 2)? as usize, at + 4)
+    } else {
+        (first, at + 2)
+    };
+
+    let units = (0..len)
+        .map(|i| u16_at(data, at + i * 2))
+        .collect::<Result<Vec<u16>, String>>()?;
+    Ok(String::from_utf16_lossy(&units))
+}
+
+// android:label of <application> as (value type, value data, raw string)
+fn manifest_label(xml: &[u8]) -> Result<Option<(u8, u32, Option<String>)>, String> {
+    if u16_at(xml, 0)? != RES_XML {
+        return Err("not a binary xml file".to_string());
+    }
+
+    let mut strings = Vec::new();
+    let mut resource_ids = Vec::new();
+    let mut offset = u16_at(xml, 2)? as usize;
+
+    while offset + 8 <= xml.len() {
+        let chunk_type = u16_at(xml, offset)?;
+        let header_size = u16_at(xml, offset + 2)? as usize;
+        let size = u32_at(xml, offset + 4)? as usize;
+        if size < 8 {
+            return Err("corrupt xml chunk".to_string());
+        }
+
+        match chunk_type {
+            RES_STRING_POOL => strings = string_pool(xml, offset)?,
+            RES_XML_RESOURCE_MAP => {
+                resource_ids = (offset + header_size..offset + size)
+                    .step_by(4)
+                    .map(|at| u32_at(xml, at))
+                    .collect::<Result<_, _>>()?;
+            }
+            RES_XML_START_ELEMENT => {
+                let ext = offset + header_size;
+                let name = u32_at(xml, ext + 4)? as usize;
+
+                if strings.get(name).map(String::as_str) == Some("application") {
+                    let attr_start = u16_at(xml, ext + 8)? as usize;
+                    let attr_size = u16_at(xml, ext + 10)? as usize;
+                    let attr_count = u16_at(xml, ext + 12)? as usize;
+
+                    for i in 0..attr_count {
+                        let attr = ext + attr_start + i * attr_size;
+                        let attr_name = u32_at(xml, attr + 4)? as usize;
+                        // obfuscated manifests drop the names, the resource map still has the id
+                        let is_label = resource_ids.get(attr_name) == Some(&LABEL_ATTR)
+                            || strings.get(attr_name).map(String::as_str) == Some("label");
+                        if !is_label {
+                            continue;
+                        }
+
+                        let raw = u32_at(xml, attr + 8)?;
+                        let data_type = u8_at(xml, attr + 15)?;
+                        let data = u32_at(xml, attr + 16)?;
+                        let raw = strings.get(raw as usize)
+                            .or_else(|| if data_type == TYPE_STRING { strings.get(data as usize) } else { None })
+                            .cloned();
+                        return Ok(Some((data_type, data, raw)));
+                    }
+                    return Ok(None);
+                }
+            }
+            _ => {}
+        }
+
+        offset += size;
+    }
+
+    Ok(None)
+}
+
+// a value in one configuration of the table
+struct Candidate {
+    data_type: u8,
+    data: u32,
+    // 0 for the default config, 1 for english, 2 for anything else
+    rank: u8,
+}
+
+// looks up a string resource, following references a few levels deep
+fn resolve_string(table: &[u8], mut id: u32) -> Result<Option<String>, String> {
+    if u16_at(table, 0)? != RES_TABLE {
+        return Err("not a resource table".to_string());
+    }
+
+    let mut global_strings = Vec::new();
+    let mut packages = Vec::new();
+    let mut offset = u16_at(table, 2)? as usize;
+    while offset + 8 <= table.len() {
+        let size = u32_at(table, offset + 4)? as usize;
+        if size < 8 {
+            return Err("corrupt resource table".to_string());
+        }
+        match u16_at(table, offset)? {
+            RES_STRING_POOL => global_strings = string_pool(table, offset)?,
+            RES_TABLE_PACKAGE => packages.push(offset),
+            _ => {}
+        }
+        offset += size;
+    }
+
+    for _ in 0..4 {
+        let mut best: Option<Candidate> = None;
+        for &package in &packages {
+            if u32_at(table, package + 8)? == id >> 24 {
+                best = find_entry(table, package, id)?;
+                break;
+            }
+        }
+
+        match best {
+            Some(c) if c.data_type == TYPE_STRING => return Ok(global_strings.get(c.data as usize).cloned()),
+            Some(c) if c.data_type == TYPE_REFERENCE => id = c.data,
+            _ => return Ok(None),
+        }
+    }
+
+    Ok(None)
+}
+
+fn find_entry(table: &[u8], package: usize, id: u32) -> Result<Option<Candidate>, String> {
+    let type_id = ((id >> 16) & 0xff) as u8;
+    let entry_index = (id & 0xffff) as usize;
+
+    let package_end = package + u32_at(table, package + 4)? as usize;
+    let mut offset = package + u16_at(table, package + 2)? as usize;
+    let mut best: Option<Candidate> = None;
+
+    while offset + 8 <= package_end {
+        let size = u32_at(table, offset + 4)? as usize;
+        if size < 8 {
+            return Err("corrupt resource table".to_string());
+        }
+
+        if u16_at(table, offset)? == RES_TABLE_TYPE && u8_at(table, offset + 8)? == type_id {
+            if let Some(candidate) = type_entry(table, offset, entry_index)? {
+                if best.as_ref().is_none_or(|b| candidate.rank < b.rank) {
+                    best = Some(candidate);
+                }
+            }
+        }
+
+        offset += size;
+    }
+
+    Ok(best)
+}
+
+// the value of one entry in a ResTable_type chunk, if this config has it
+fn type_entry(table: &[u8], chunk: usize, index: usize) -> Result<Option<Candidate>, String> {
+    const FLAG_SPARSE: u8 = 0x01;
+    const FLAG_OFFSET16: u8 = 0x02;
+    const ENTRY_COMPLEX: u16 = 0x0001;
+    const ENTRY_COMPACT: u16 = 0x0008;
+
+    let header_size = u16_at(table, chunk + 2)? as usize;
+    let flags = u8_at(table, chunk + 9)?;
+    let entry_count = u32_at(table, chunk + 12)? as usize;
+    let entries_start = chunk + u32_at(table, chunk + 16)? as usize;
+    let offsets = chunk + header_size;
+
+    // the config starts at 20: size, mcc, mnc, then the language
+    let language = [u8_at(table, chunk + 28)?, u8_at(table, chunk + 29)?];
+    let rank = match &language {
+        [0, 0] => 0,
+        b"en" => 1,
+        _ => 2,
+    };
+
+    let entry_offset = if flags & FLAG_SPARSE != 0 {
+        // (index, offset / 4) pairs sorted by index
+        let mut found = None;
+        for i in 0..entry_count {
+            if u16_at(table, offsets + i * 4)? as usize == index {
+                found = Some(u16_at(table, offsets + i * 4 + 2)? as usize * 4);
+                break;
+            }
+        }
+        match found {
+            Some(offset) => offset,
+            None => return Ok(None),
+        }
+    } else if index >= entry_count {
+        return Ok(None);
+    } else if flags & FLAG_OFFSET16 != 0 {
+        match u16_at(table, offsets + index * 2)? {
+            0xffff => return Ok(None),
+            offset => offset as usize * 4,
+        }
+    } else {
+        match u32_at(table, offsets + index * 4)? {
+            0xffff_ffff => return Ok(None),
+            offset => offset as usize,
+        }
+    };
+
+    let entry = entries_start + entry_offset;
+    let entry_flags = u16_at(table, entry + 2)?;
+
+    if entry_flags & ENTRY_COMPACT != 0 {
+        // key index, flags with the value type in the high byte, data
+        return Ok(Some(Candidate {
+            data_type: (entry_flags >> 8) as u8,
+            data: u32_at(table, entry + 4)?,
+            rank,
+        }));
+    }
+    if entry_flags & ENTRY_COMPLEX != 0 {
+        return Ok(None);
+    }
+
+    let value = entry + u16_at(table, entry)? as usize;
+    Ok(Some(Candidate {
+        data_type: u8_at(table, value + 3)?,
+        data: u32_at(table, value + 4)?,
+        rank,
+    }))
+}
+
+// builders for the test apks, also used by the fake device tests in main.rs
+#[cfg(test)]
+pub mod testing {
+    fn chunk(chunk_type: u16, header: &[u8], body: &[u8]) -> Vec<u8> {
+        let header_size = 8 + header.len();
+        let mut out = Vec::new();
+        out.extend(chunk_type.to_le_bytes());
+        out.extend((header_size as u16).to_le_bytes());
+        out.extend(((header_size + body.len()) as u32).to_le_bytes());
+        out.extend(header);
+        out.extend(body);
+        out
+    }
+
+    // utf-8 string pool
+    fn string_pool(strings: &[&str]) -> Vec<u8> {
+        let mut offsets = Vec::new();
+        let mut data = Vec::new();
+        for s in strings {
+            offsets.extend((data.len() as u32).to_le_bytes());
+            data.push(s.chars().count() as u8);
+            data.push(s.len() as u8);
+            data.extend(s.as_bytes());
+            data.push(0);
+        }
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+
+        let mut header = Vec::new();
+        header.extend((strings.len() as u32).to_le_bytes());
+        header.extend(0u32.to_le_bytes());
+        header.extend((1u32 << 8).to_le_bytes());
+        header.extend((28 + offsets.len() as u32).to_le_bytes());
+        header.extend(0u32.to_le_bytes());
+        chunk(super::RES_STRING_POOL, &header, &[offsets, data].concat())
+    }
+
+    fn value(data_type: u8, data: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend(8u16.to_le_bytes());
+        out.push(0);
+        out.push(data_type);
+        out.extend(data.to_le_bytes());
+        out
+    }
+
+    // <manifest><application android:label=...> with either a string or a reference
+    pub fn manifest(label: Result<&str, u32>) -> Vec<u8> {
+        let (raw, data_type, data) = match label {
+            Ok(_) => (2u32, super::TYPE_STRING, 2u32),
+            Err(id) => (u32::MAX, super::TYPE_REFERENCE, id),
+        };
+        let strings = string_pool(&["label", "application", label.unwrap_or("")]);
+        let resource_map = chunk(super::RES_XML_RESOURCE_MAP, &[], &super::LABEL_ATTR.to_le_bytes());
+
+        let mut ext = Vec::new();
+        ext.extend(u32::MAX.to_le_bytes());
+        ext.extend(1u32.to_le_bytes());
+        ext.extend(20u16.to_le_bytes());
+        ext.extend(20u16.to_le_bytes());
+        ext.extend(1u16.to_le_bytes());
+        ext.extend([0u8; 6]);
+        ext.extend(u32::MAX.to_le_bytes());
+        ext.extend(0u32.to_le_bytes());
+        ext.extend(raw.to_le_bytes());
+        ext.extend(value(data_type, data));
+
+        let mut line = Vec::new();
+        line.extend(1u32.to_le_bytes());
+        line.extend(u32::MAX.to_le_bytes());
+        let element = chunk(super::RES_XML_START_ELEMENT, &line, &ext);
+
+        chunk(super::RES_XML, &[], &[strings, resource_map, element].concat())
+    }
+
+    // a table with package 0x7f whose string type (id 1) holds `entries`,
+    // each a list of (language, string) values; "" is the default config
+    pub fn resources(entries: &[&[(&str, &str)]]) -> Vec<u8> {
+        let mut strings = Vec::new();
+        for entry in entries {
+            for (_, s) in entry.iter() {
+                strings.push(*s);
+            }
+        }
+
+        let languages: Vec<&str> = {
+            let mut l: Vec<&str> = entries.iter().flat_map(|e| e.iter().map(|(l, _)| *l)).collect();
+            l.sort();
+            l.dedup();
+            l
+        };
+
+        let mut types = Vec::new();
+        for language in languages {
+            let mut offsets = Vec::new();
+            let mut data = Vec::new();
+            let mut string_index = 0u32;
+            for entry in entries {
+                let found = entry.iter().position(|(l, _)| *l == language);
+                match found {
+                    Some(i) => {
+                        offsets.extend((data.len() as u32).to_le_bytes());
+                        data.extend(8u16.to_le_bytes());
+                        data.extend(0u16.to_le_bytes());
+                        data.extend(0u32.to_le_bytes());
+                        data.extend(value(super::TYPE_STRING, string_index + i as u32));
+                    }
+                    None => offsets.extend(u32::MAX.to_le_bytes()),
+                }
+                string_index += entry.len() as u32;
+            }
+
+            let mut config = vec![0u8; 64];
+            config[..4].copy_from_slice(&64u32.to_le_bytes());
+            config[8..8 + language.len()].copy_from_slice(language.as_bytes());
+
+            let mut header = vec![1u8, 0, 0, 0];
+            header.extend((entries.len() as u32).to_le_bytes());
+            header.extend((8 + 12 + 64 + offsets.len() as u32).to_le_bytes());
+            header.extend(config);
+            types.extend(chunk(super::RES_TABLE_TYPE, &header, &[offsets, data].concat()));
+        }
+
+        let mut header = Vec::new();
+        header.extend(0x7fu32.to_le_bytes());
+        header.extend([0u8; 256]);
+        header.extend([0u8; 16]);
+        let package = chunk(super::RES_TABLE_PACKAGE, &header, &types);
+
+        chunk(super::RES_TABLE, &1u32.to_le_bytes(), &[string_pool(&strings), package].concat())
+    }
+
+    // a zip with stored (uncompressed) entries
+    pub fn zip(files: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut directory = Vec::new();
+
+        for (name, data) in files {
+            let offset = out.len() as u32;
+            out.extend(0x0403_4b50u32.to_le_bytes());
+            out.extend([0u8; 14]);
+            out.extend((data.len() as u32).to_le_bytes());
+            out.extend((data.len() as u32).to_le_bytes());
+            out.extend((name.len() as u16).to_le_bytes());
+            out.extend(0u16.to_le_bytes());
+            out.extend(name.as_bytes());
+            out.extend(data);
+
+            directory.extend(0x0201_4b50u32.to_le_bytes());
+            directory.extend([0u8; 16]);
+            directory.extend((data.len() as u32).to_le_bytes());
+            directory.extend((data.len() as u32).to_le_bytes());
+            directory.extend((name.len() as u16).to_le_bytes());
+            directory.extend([0u8; 12]);
+            directory.extend(offset.to_le_bytes());
+            directory.extend(name.as_bytes());
+        }
+
+        let directory_offset = out.len() as u32;
+        out.extend(&directory);
+        out.extend(0x0605_4b50u32.to_le_bytes());
+        out.extend([0u8; 4]);
+        out.extend((files.len() as u16).to_le_bytes());
+        out.extend((files.len() as u16).to_le_bytes());
+        out.extend((directory.len() as u32).to_le_bytes());
+        out.extend(directory_offset.to_le_bytes());
+        out.extend(0u16.to_le_bytes());
+        out
+    }
+
+    pub fn apk(label: &str) -> Vec<u8> {
+        zip(&[("AndroidManifest.xml", manifest(Ok(label)))])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::*;
+    use super::*;
+
+    #[test]
+    fn reads_plain_string_label() {
+        assert_eq!(read_label(&apk("Easter Egg")).unwrap().as_deref(), Some("Easter Egg"));
+    }
+
+    #[test]
+    fn resolves_label_resource_preferring_default_config() {
+        let apk = zip(&[
+            ("AndroidManifest.xml", manifest(Err(0x7f01_0001))),
+            ("resources.arsc", resources(&[
+                &[("", "unused")],
+                &[("de", "Wetter"), ("", "Weather"), ("en", "Weather (en)")],
+            ])),
+        ]);
+        assert_eq!(read_label(&apk).unwrap().as_deref(), Some("Weather"));
+
+        let apk = zip(&[
+            ("AndroidManifest.xml", manifest(Err(0x7f01_0000))),
+            ("resources.arsc", resources(&[&[("fr", "Météo"), ("en", "Weather")]])),
+        ]);
+        assert_eq!(read_label(&apk).unwrap().as_deref(), Some("Weather"));
+    }
+
+    #[test]
+    fn reads_deflated_entries() {
+        let manifest = manifest(Ok("Camera"));
+        let mut apk = zip(&[("AndroidManifest.xml", manifest.clone())]);
+        let compressed = miniz_oxide::deflate::compress_to_vec(&manifest, 6);
+
+        // rewrite the single entry as deflated: method, sizes, data, directory offset
+        let mut deflated = apk[..30 + 19].to_vec();
+        deflated[8] = 8;
+        deflated[18..22].copy_from_slice(&(compressed.len() as u32).to_le_bytes());
+        deflated.extend(&compressed);
+        let directory = apk.split_off(30 + 19 + manifest.len());
+        let directory_offset = deflated.len() as u32;
+        deflated.extend(&directory);
+        let eocd = deflated.len() - 22;
+        deflated[directory_offset as usize + 10] = 8;
+        deflated[directory_offset as usize + 20..directory_offset as usize + 24]
+            .copy_from_slice(&(compressed.len() as u32).to_le_bytes());
+        deflated[eocd + 16..eocd + 20].copy_from_slice(&directory_offset.to_le_bytes());
+
+        assert_eq!(read_label(&deflated).unwrap().as_deref(), Some("Camera"));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(read_label(b"definitely not a zip").is_err());
+        assert!(read_label(&zip(&[("classes.dex", vec![1, 2, 3])])).is_err());
+
+        // a pool header claiming four billion strings fails instead of allocating for them
+        let mut pool = vec![0u8; 28];
+        pool[2..4].copy_from_slice(&28u16.to_le_bytes());
+        pool[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(super::string_pool(&pool, 0).is_err());
+    }
+}
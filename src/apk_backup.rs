@@ -0,0 +1,83 @@
+// apk files copied into a backup so an app can be reinstalled once the device no longer has it
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ApkFile {
+    // relative to the backup's apk folder
+    pub file: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+// `pm path` prints one "package:/path/to/file.apk" line per apk, base first then splits
+pub fn parse_pm_path(output: &str) -> Vec<String> {
+    output.lines()
+        .filter_map(|l| l.trim().strip_prefix("package:"))
+        .filter(|p| !p.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// "base.apk", "split_config.arm64_v8a.apk", ...
+pub fn file_name(remote: &str) -> &str {
+    remote.rsplit('/').next().unwrap_or(remote)
+}
+
+pub fn sha256_hex(content: &[u8]) -> String {
+    Sha256::digest(content).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// checksums a pulled file for the backup
+pub fn describe(dir: &Path, file: &str) -> Result<ApkFile, String> {
+    let content = fs::read(dir.join(file)).map_err(|e| format!("{}: {}", file, e))?;
+    Ok(ApkFile {
+        file: file.to_string(),
+        sha256: sha256_hex(&content),
+        size: content.len() as u64,
+    })
+}
+
+// fails when a file is missing or no longer matches its checksum
+pub fn verify(dir: &Path, files: &[ApkFile]) -> Result<(), String> {
+    if files.is_empty() {
+        return Err("no apks in the backup".to_string());
+    }
+    for apk in files {
+        let content = fs::read(dir.join(&apk.file)).map_err(|e| format!("{}: {}", apk.file, e))?;
+        if sha256_hex(&content) != apk.sha256 {
+            return Err(format!("{}: checksum mismatch", apk.file));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_split_paths_and_checks_sums() {
+        let output = "package:/data/app/~~a==/com.spotify.music-b==/base.apk\n\
+            package:/data/app/~~a==/com.spotify.music-b==/split_config.arm64_v8a.apk\n";
+        let paths = parse_pm_path(output);
+        assert_eq!(paths.len(), 2);
+        assert_eq!(file_name(&paths[1]), "split_config.arm64_v8a.apk");
+
+        assert_eq!(sha256_hex(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+        let dir = std::env::temp_dir().join(format!("android_debloater_test_{}_apk_sums", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("base.apk"), b"abc").unwrap();
+        let files = vec![describe(&dir, "base.apk").unwrap()];
+        assert_eq!(files[0].size, 3);
+        assert!(verify(&dir, &files).is_ok());
+
+        fs::write(dir.join("base.apk"), b"abd").unwrap();
+        assert!(verify(&dir, &files).unwrap_err().contains("checksum"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,257 @@
+// device access goes through this trait so the flows can run against a fake device
+
+use crate::error::AdbError;
+use crate::session::{ShellSession, ShellStream, TimedReader, COMMAND_TIMEOUT};
+use std::io;
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+// one line of `adb devices -l`
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceEntry {
+    pub serial: String,
+    // "device", "offline", "unauthorized", ...
+    pub state: String,
+    // the rest of the line (usb path, model, transport id)
+    pub details: String,
+}
+
+impl DeviceEntry {
+    pub fn is_ready(&self) -> bool {
+        self.state == "device"
+    }
+
+    pub fn is_wireless(&self) -> bool {
+        self.serial.contains(':')
+    }
+
+    // the model:... field of the details, underscores as adb prints them
+    pub fn model(&self) -> Option<&str> {
+        self.details.split_whitespace().find_map(|f| f.strip_prefix("model:"))
+    }
+}
+
+pub trait DeviceBackend: Send + Sync {
+    fn devices(&self) -> Result<Vec<DeviceEntry>, AdbError>;
+
+    // runs `adb shell <args>` on the given device (or the only one when None)
+    fn shell(&self, serial: Option<&str>, args: &[&str]) -> Result<String, AdbError>;
+
+    fn pair(&self, address: &str, code: &str) -> Result<String, AdbError>;
+
+    fn connect(&self, address: &str) -> Result<String, AdbError>;
+
+    fn disconnect(&self, address: &str) -> Result<String, AdbError>;
+
+    fn tcpip(&self, serial: Option<&str>, port: u16) -> Result<String, AdbError>;
+
+    // copies a file from the device to `local`
+    fn pull(&self, serial: Option<&str>, remote: &str, local: &Path) -> Result<String, AdbError>;
+
+    // installs one app from its base and split apks for `user`
+    fn install_multiple(&self, serial: Option<&str>, apks: &[&Path], user: u32) -> Result<String, AdbError>;
+
+    // a shell kept open for many commands, instead of one adb shell each
+    fn open_session(&self, serial: Option<&str>) -> Result<Box<dyn ShellSession>, AdbError>;
+}
+
+// the real backend, shells out to the adb binary
+pub struct AdbBackend;
+
+impl AdbBackend {
+    fn run(&self, serial: Option<&str>, args: &[&str]) -> Result<String, AdbError> {
+        let mut cmd = Command::new("adb");
+        if let Some(serial) = serial {
+            cmd.args(["-s", serial]);
+        }
+
+        let output = cmd
+            .args(args)
+            .output()
+            .map_err(spawn_error)?;
+
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+            let code = output.status.code();
+            if stderr.trim().is_empty() {
+                // pm writes its failures to stdout
+                Err(AdbError::from_output(&String::from_utf8_lossy(&output.stdout), code))
+            } else {
+                Err(AdbError::from_output(&stderr, code))
+            }
+        }
+    }
+}
+
+impl DeviceBackend for AdbBackend {
+    fn devices(&self) -> Result<Vec<DeviceEntry>, AdbError> {
+        let output = self.run(None, &["devices", "-l"])?;
+        Ok(parse_devices(&output))
+    }
+
+    fn shell(&self, serial: Option<&str>, args: &[&str]) -> Result<String, AdbError> {
+        let mut full = vec!["shell"];
+        full.extend_from_slice(args);
+        self.run(serial, &full)
+    }
+
+    fn pair(&self, address: &str, code: &str) -> Result<String, AdbError> {
+        let output = self.run(None, &["pair", address, code])?;
+        if output.contains("Successfully paired") {
+            Ok(output)
+        } else {
+            Err(AdbError::Other(output.trim().to_string()))
+        }
+    }
+
+    fn connect(&self, address: &str) -> Result<String, AdbError> {
+        // adb connect exits 0 even when it could not connect
+        let output = self.run(None, &["connect", address])?;
+        if output.contains("connected to") {
+            Ok(output)
+        } else {
+            Err(AdbError::Other(output.trim().to_string()))
+        }
+    }
+
+    fn disconnect(&self, address: &str) -> Result<String, AdbError> {
+        self.run(None, &["disconnect", address])
+    }
+
+    fn tcpip(&self, serial: Option<&str>, port: u16) -> Result<String, AdbError> {
+        self.run(serial, &["tcpip", &port.to_string()])
+    }
+
+    fn pull(&self, serial: Option<&str>, remote: &str, local: &Path) -> Result<String, AdbError> {
+        self.run(serial, &["pull", remote, &local.to_string_lossy()])
+    }
+
+    fn install_multiple(&self, serial: Option<&str>, apks: &[&Path], user: u32) -> Result<String, AdbError> {
+        let user = user.to_string();
+        let files: Vec<String> = apks.iter().map(|p| p.to_string_lossy().to_string()).collect();
+        let mut args = vec!["install-multiple", "-r", "--user", &user];
+        args.extend(files.iter().map(String::as_str));
+
+        // like pm, a failed install can still exit 0
+        let output = self.run(serial, &args)?;
+        if output.contains("Success") {
+            Ok(output)
+        } else {
+            Err(AdbError::from_output(&output, None))
+        }
+    }
+
+    fn open_session(&self, serial: Option<&str>) -> Result<Box<dyn ShellSession>, AdbError> {
+        let mut cmd = Command::new("adb");
+        if let Some(serial) = serial {
+            cmd.args(["-s", serial]);
+        }
+        // stdin is a pipe, so adb gives sh no terminal and nothing is echoed back
+        let mut child = cmd.args(["shell", "sh"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(spawn_error)?;
+
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(AdbError::Other("adb shell has no stdin or stdout".to_string()));
+        };
+        Ok(Box::new(AdbShellProcess {
+            stream: ShellStream::new(TimedReader::new(stdout, COMMAND_TIMEOUT), stdin),
+            child,
+            hung: false,
+        }))
+    }
+}
+
+// an `adb shell sh` process fed one command at a time
+struct AdbShellProcess {
+    stream: ShellStream<TimedReader, ChildStdin>,
+    child: Child,
+    // a command timed out, the shell would never get to an exit
+    hung: bool,
+}
+
+impl ShellSession for AdbShellProcess {
+    fn run(&mut self, args: &[&str]) -> io::Result<(String, i32)> {
+        let result = self.stream.run(args);
+        self.hung |= result.as_ref().is_err_and(|e| e.kind() == io::ErrorKind::TimedOut);
+        result
+    }
+}
+
+impl Drop for AdbShellProcess {
+    fn drop(&mut self) {
+        if self.hung {
+            let _ = self.child.kill();
+        } else {
+            self.stream.close();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+fn spawn_error(e: io::Error) -> AdbError {
+    if e.kind() == io::ErrorKind::NotFound {
+        AdbError::AdbMissing(String::new())
+    } else {
+        AdbError::AdbMissing(format!("failed to execute adb: {}", e))
+    }
+}
+
+pub fn parse_devices(output: &str) -> Vec<DeviceEntry> {
+    let mut devices = Vec::new();
+
+    for line in output.lines() {
+        if line.starts_with("List of") || line.starts_with('*') {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        if let (Some(serial), Some(state)) = (parts.next(), parts.next()) {
+            devices.push(DeviceEntry {
+                serial: serial.to_string(),
+                state: state.to_string(),
+                details: parts.collect::<Vec<_>>().join(" "),
+            });
+        }
+    }
+
+    devices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_devices_listing() {
+        let output = "List of devices attached\n\
+            R58M123ABC             device usb:1-1 product:beyond1 model:SM_G973F transport_id:1\n\
+            192.168.1.20:5555      offline transport_id:2\n\
+            emulator-5554          unauthorized transport_id:3\n\n";
+
+        let devices = parse_devices(output);
+        assert_eq!(devices.len(), 3);
+        assert_eq!(devices[0].serial, "R58M123ABC");
+        assert!(devices[0].is_ready());
+        assert_eq!(devices[0].details, "usb:1-1 product:beyond1 model:SM_G973F transport_id:1");
+        assert_eq!(devices[0].model(), Some("SM_G973F"));
+        assert_eq!(devices[1].model(), None);
+        assert!(devices[1].is_wireless());
+        assert!(!devices[1].is_ready());
+        assert_eq!(devices[2].state, "unauthorized");
+    }
+
+    #[test]
+    fn skips_daemon_banner() {
+        let output = "* daemon not running; starting now at tcp:5037\n\
+            * daemon started successfully\n\
+            List of devices attached\n";
+
+        assert!(parse_devices(output).is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+// bloatware definitions: the built-in list plus json files from the config dir or --list.
+// files can be in our own format or in UAD's uad_lists.json format
+
+use crate::uad;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_LIST: &str = include_str!("../lists/default.json");
+
+// how safe a package is to remove, from least to most risky
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Removal {
+    Recommended,
+    Advanced,
+    Expert,
+    Unsafe,
+}
+
+impl Removal {
+    pub fn parse(s: &str) -> Option<Removal> {
+        match s.to_lowercase().as_str() {
+            "recommended" => Some(Removal::Recommended),
+            "advanced" => Some(Removal::Advanced),
+            "expert" => Some(Removal::Expert),
+            "unsafe" => Some(Removal::Unsafe),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Removal::Recommended => "recommended",
+            Removal::Advanced => "advanced",
+            Removal::Expert => "expert",
+            Removal::Unsafe => "unsafe",
+        }
+    }
+}
+
+fn default_removal() -> Removal {
+    Removal::Advanced
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BloatEntry {
+    pub id: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub vendor: String,
+    #[serde(default = "default_removal")]
+    pub removal: Removal,
+    #[serde(default)]
+    pub notes: String,
+    // package ids this one relies on / that rely on it (from UAD lists)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub needed_by: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ListFile {
+    packages: Vec<BloatEntry>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BloatList {
+    entries: BTreeMap<String, BloatEntry>,
+}
+
+impl BloatList {
+    pub fn builtin() -> Self {
+        let mut list = BloatList::default();
+        let file: ListFile = serde_json::from_str(DEFAULT_LIST).expect("built-in bloat list is valid json");
+        list.merge(file.packages);
+        list
+    }
+
+    // later entries replace earlier ones with the same id
+    pub fn merge(&mut self, entries: Vec<BloatEntry>) {
+        for entry in entries {
+            self.entries.insert(entry.id.clone(), entry);
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&BloatEntry> {
+        self.entries.get(id)
+    }
+
+    pub fn removal(&self, id: &str) -> Option<Removal> {
+        self.get(id).map(|e| e.removal)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &BloatEntry> {
+        self.entries.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+pub fn load_list_file(path: &Path) -> Result<Vec<BloatEntry>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse_list(&content).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn parse_list(content: &str) -> Result<Vec<BloatEntry>, String> {
+    match serde_json::from_str::<ListFile>(content) {
+        Ok(file) => Ok(file.packages),
+        Err(own_error) => uad::parse_uad(content)
+            .map_err(|_| own_error.to_string()),
+    }
+}
+
+// *.json files in a directory, sorted so the merge order is stable
+pub fn list_files_in(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().and_then(|s| s.to_str()) == Some("json"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_list_loads() {
+        let list = BloatList::builtin();
+        assert!(list.len() >= 29);
+        let entry = list.get("com.samsung.android.messaging").unwrap();
+        assert_eq!(entry.vendor, "Samsung");
+        assert_eq!(entry.removal, Removal::Expert);
+    }
+
+    #[test]
+    fn detects_uad_lists() {
+        let entries = parse_list(r#"[{"id": "com.oem.app", "list": "Oem", "description": "x",
+            "dependencies": [], "neededBy": [], "labels": [], "removal": "Advanced"}]"#).unwrap();
+        assert_eq!(entries[0].removal, Removal::Advanced);
+
+        let entries = parse_list(r#"{"packages": [{"id": "com.oem.app"}]}"#).unwrap();
+        assert_eq!(entries[0].id, "com.oem.app");
+
+        assert!(parse_list(r#"{"packages": "nope"}"#).is_err());
+    }
+
+    #[test]
+    fn user_entries_override_builtin() {
+        let mut list = BloatList::builtin();
+        let file: ListFile = serde_json::from_str(r#"{"packages": [
+            {"id": "com.android.egg", "description": "keep the egg", "removal": "unsafe"},
+            {"id": "com.oem.weather"}
+        ]}"#).unwrap();
+        list.merge(file.packages);
+
+        assert_eq!(list.get("com.android.egg").unwrap().description, "keep the egg");
+        assert_eq!(list.get("com.android.egg").unwrap().removal, Removal::Unsafe);
+        assert_eq!(list.get("com.oem.weather").unwrap().removal, Removal::Advanced);
+    }
+}
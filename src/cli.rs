@@ -0,0 +1,402 @@
+// command line parsing for scripted (non-interactive) use
+
+use crate::bloatlist::Removal;
+use crate::packages::ListMode;
+use std::path::PathBuf;
+
+pub enum CliCommand {
+    Interactive,
+    Help,
+    Version,
+    List { bloat: bool, mode: ListMode },
+    Search { term: String },
+    Remove { packages: Vec<String>, force: bool, no_backup: bool },
+    Restore { packages: Vec<String>, backup: Option<String> },
+    Backup { bloat: bool },
+    Snapshot { output: Option<String> },
+    // `to` None compares against the live device
+    Diff { from: String, to: Option<String> },
+    Revert { snapshot: String, force: bool },
+    Apply { profile: String, force: bool, no_backup: bool },
+    Export { output: String },
+    Devices,
+    Users,
+    Info,
+}
+
+// options that apply to every command and to the interactive menu
+pub struct Options {
+    pub serial: Option<String>,
+    pub dry_run: bool,
+    // extra bloat list files, merged after the config dir ones
+    pub lists: Vec<PathBuf>,
+    pub max_tier: Removal,
+    // android users to work on, empty for the owner (user 0)
+    pub users: Vec<u32>,
+    pub all_users: bool,
+    // where backups are kept, None for the data dir
+    pub backup_dir: Option<PathBuf>,
+    // where snapshots are kept, None for the data dir
+    pub snapshot_dir: Option<PathBuf>,
+    // copy the apks into removal backups
+    pub pull_apks: bool,
+    // show app labels, pulling apks for the ones not cached yet
+    pub read_labels: bool,
+    // run on every attached device at once
+    pub all_devices: bool,
+    // run the adb binary for every command instead of talking to the adb server directly
+    pub adb_binary: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            serial: None,
+            dry_run: false,
+            lists: Vec::new(),
+            max_tier: Removal::Recommended,
+            users: Vec::new(),
+            all_users: false,
+            backup_dir: None,
+            snapshot_dir: None,
+            pull_apks: false,
+            read_labels: false,
+            all_devices: false,
+            adb_binary: false,
+        }
+    }
+}
+
+pub struct Cli {
+    pub command: CliCommand,
+    pub options: Options,
+}
+
+pub fn parse_args(args: &[String]) -> Result<Cli, String> {
+    let mut options = Options {
+        serial: std::env::var("ANDROID_SERIAL").ok().filter(|s| !s.is_empty()),
+        backup_dir: std::env::var_os("ANDROID_DEBLOATER_BACKUP_DIR").filter(|d| !d.is_empty()).map(PathBuf::from),
+        snapshot_dir: std::env::var_os("ANDROID_DEBLOATER_SNAPSHOT_DIR").filter(|d| !d.is_empty()).map(PathBuf::from),
+        ..Options::default()
+    };
+    let mut remaining = Vec::new();
+
+    // global options may appear anywhere
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "-s" || arg == "--serial" {
+            match iter.next() {
+                Some(value) => options.serial = Some(value.clone()),
+                None => return Err(format!("{} needs a device serial", arg)),
+            }
+        } else if let Some(value) = arg.strip_prefix("--serial=") {
+            options.serial = Some(value.to_string());
+        } else if arg == "--dry-run" || arg == "-n" {
+            options.dry_run = true;
+        } else if arg == "--pull-apks" {
+            options.pull_apks = true;
+        } else if arg == "--read-labels" {
+            options.read_labels = true;
+        } else if arg == "--all-devices" {
+            options.all_devices = true;
+        } else if arg == "--adb-binary" {
+            options.adb_binary = true;
+        } else if arg == "--list" {
+            match iter.next() {
+                Some(value) => options.lists.push(PathBuf::from(value)),
+                None => return Err("--list needs a file".to_string()),
+            }
+        } else if let Some(value) = arg.strip_prefix("--list=") {
+            options.lists.push(PathBuf::from(value));
+        } else if arg == "--max-tier" {
+            match iter.next() {
+                Some(value) => options.max_tier = parse_tier(value)?,
+                None => return Err("--max-tier needs a tier".to_string()),
+            }
+        } else if let Some(value) = arg.strip_prefix("--max-tier=") {
+            options.max_tier = parse_tier(value)?;
+        } else if arg == "--user" {
+            match iter.next() {
+                Some(value) => add_user(&mut options, value)?,
+                None => return Err("--user needs a user id or 'all'".to_string()),
+            }
+        } else if let Some(value) = arg.strip_prefix("--user=") {
+            add_user(&mut options, value)?;
+        } else if arg == "--backup-dir" {
+            match iter.next() {
+                Some(value) => options.backup_dir = Some(PathBuf::from(value)),
+                None => return Err("--backup-dir needs a directory".to_string()),
+            }
+        } else if let Some(value) = arg.strip_prefix("--backup-dir=") {
+            options.backup_dir = Some(PathBuf::from(value));
+        } else if arg == "--snapshot-dir" {
+            match iter.next() {
+                Some(value) => options.snapshot_dir = Some(PathBuf::from(value)),
+                None => return Err("--snapshot-dir needs a directory".to_string()),
+            }
+        } else if let Some(value) = arg.strip_prefix("--snapshot-dir=") {
+            options.snapshot_dir = Some(PathBuf::from(value));
+        } else {
+            remaining.push(arg.as_str());
+        }
+    }
+
+    let command = parse_command(&remaining)?;
+    Ok(Cli { command, options })
+}
+
+fn parse_command(args: &[&str]) -> Result<CliCommand, String> {
+    let (name, rest) = match args.split_first() {
+        Some((name, rest)) => (*name, rest.to_vec()),
+        None => return Ok(CliCommand::Interactive),
+    };
+
+    let command = match name {
+        "-h" | "--help" | "help" => CliCommand::Help,
+        "-V" | "--version" | "version" => CliCommand::Version,
+        "list" => {
+            let modes = [
+                ("--system", ListMode::System),
+                ("--third-party", ListMode::ThirdParty),
+                ("--disabled", ListMode::Disabled),
+                ("--enabled", ListMode::Enabled),
+                ("--uninstalled", ListMode::Uninstalled),
+                ("--all", ListMode::All),
+            ];
+            let mut known: Vec<&str> = modes.iter().map(|(flag, _)| *flag).collect();
+            known.push("--bloat");
+
+            let (flags, positional) = split_flags(&rest, &known)?;
+            no_positional("list", &positional)?;
+            if flags.len() > 1 {
+                return Err("list: give only one of --bloat, --system, --third-party, --disabled, \
+                    --enabled, --uninstalled, --all".to_string());
+            }
+            let mode = modes.iter()
+                .find(|(flag, _)| flags.contains(flag))
+                .map(|(_, mode)| *mode)
+                .unwrap_or(ListMode::System);
+            CliCommand::List { bloat: flags.contains(&"--bloat"), mode }
+        }
+        "search" => {
+            let (_, positional) = split_flags(&rest, &[])?;
+            match positional.as_slice() {
+                [term] => CliCommand::Search { term: term.to_string() },
+                [] => return Err("search: missing search term".to_string()),
+                _ => return Err("search: expected a single search term".to_string()),
+            }
+        }
+        "remove" => {
+            let (flags, positional) = split_flags(&rest, &["--force", "--no-backup"])?;
+            if positional.is_empty() {
+                return Err("remove: no packages given".to_string());
+            }
+            CliCommand::Remove {
+                packages: positional.iter().map(|s| s.to_string()).collect(),
+                force: flags.contains(&"--force"),
+                no_backup: flags.contains(&"--no-backup"),
+            }
+        }
+        "restore" => {
+            let mut backup = None;
+            let mut packages = Vec::new();
+            let mut i = 0;
+            while i < rest.len() {
+                match rest[i] {
+                    "--backup" => {
+                        i += 1;
+                        match rest.get(i) {
+                            Some(value) => backup = Some(value.to_string()),
+                            None => return Err("restore: --backup needs a file or 'latest'".to_string()),
+                        }
+                    }
+                    flag if flag.starts_with('-') => {
+                        return Err(format!("restore: unknown option '{}'", flag));
+                    }
+                    package => packages.push(package.to_string()),
+                }
+                i += 1;
+            }
+            if backup.is_some() && !packages.is_empty() {
+                return Err("restore: give either package names or --backup, not both".to_string());
+            }
+            if backup.is_none() && packages.is_empty() {
+                return Err("restore: no packages given".to_string());
+            }
+            CliCommand::Restore { packages, backup }
+        }
+        "backup" => {
+            let (flags, positional) = split_flags(&rest, &["--bloat"])?;
+            no_positional("backup", &positional)?;
+            CliCommand::Backup { bloat: flags.contains(&"--bloat") }
+        }
+        "snapshot" => {
+            let (_, positional) = split_flags(&rest, &[])?;
+            match positional.as_slice() {
+                [] => CliCommand::Snapshot { output: None },
+                [file] => CliCommand::Snapshot { output: Some(file.to_string()) },
+                _ => return Err("snapshot: expected at most one output file".to_string()),
+            }
+        }
+        "diff" => {
+            let (_, positional) = split_flags(&rest, &[])?;
+            match positional.as_slice() {
+                [from] => CliCommand::Diff { from: from.to_string(), to: None },
+                [from, to] => CliCommand::Diff { from: from.to_string(), to: Some(to.to_string()) },
+                [] => return Err("diff: no snapshot given".to_string()),
+                _ => return Err("diff: expected one or two snapshots".to_string()),
+            }
+        }
+        "revert" => {
+            let (flags, positional) = split_flags(&rest, &["--force"])?;
+            match positional.as_slice() {
+                [snapshot] => CliCommand::Revert { snapshot: snapshot.to_string(), force: flags.contains(&"--force") },
+                [] => return Err("revert: no snapshot given".to_string()),
+                _ => return Err("revert: expected a single snapshot".to_string()),
+            }
+        }
+        "apply" => {
+            let (flags, positional) = split_flags(&rest, &["--force", "--no-backup"])?;
+            match positional.as_slice() {
+                [profile] => CliCommand::Apply {
+                    profile: profile.to_string(),
+                    force: flags.contains(&"--force"),
+                    no_backup: flags.contains(&"--no-backup"),
+                },
+                [] => return Err("apply: no profile given".to_string()),
+                _ => return Err("apply: expected a single profile file".to_string()),
+            }
+        }
+        "export" => {
+            let (_, positional) = split_flags(&rest, &[])?;
+            match positional.as_slice() {
+                [output] => CliCommand::Export { output: output.to_string() },
+                [] => return Err("export: no output file given".to_string()),
+                _ => return Err("export: expected a single output file".to_string()),
+            }
+        }
+        "devices" => {
+            let (_, positional) = split_flags(&rest, &[])?;
+            no_positional("devices", &positional)?;
+            CliCommand::Devices
+        }
+        "users" => {
+            let (_, positional) = split_flags(&rest, &[])?;
+            no_positional("users", &positional)?;
+            CliCommand::Users
+        }
+        "info" => {
+            let (_, positional) = split_flags(&rest, &[])?;
+            no_positional("info", &positional)?;
+            CliCommand::Info
+        }
+        other => return Err(format!("unknown command '{}'", other)),
+    };
+
+    Ok(command)
+}
+
+fn parse_tier(value: &str) -> Result<Removal, String> {
+    Removal::parse(value)
+        .ok_or_else(|| format!("unknown tier '{}' (recommended, advanced, expert, unsafe)", value))
+}
+
+fn add_user(options: &mut Options, value: &str) -> Result<(), String> {
+    if value == "all" {
+        options.all_users = true;
+        return Ok(());
+    }
+
+    let id = value.parse().map_err(|_| format!("bad user id '{}'", value))?;
+    if !options.users.contains(&id) {
+        options.users.push(id);
+    }
+    Ok(())
+}
+
+// separate known boolean flags from positional arguments
+fn split_flags<'a>(args: &[&'a str], known: &[&str]) -> Result<(Vec<&'a str>, Vec<&'a str>), String> {
+    let mut flags = Vec::new();
+    let mut positional = Vec::new();
+
+    for arg in args {
+        if arg.starts_with('-') {
+            if !known.contains(arg) {
+                return Err(format!("unknown option '{}'", arg));
+            }
+            flags.push(*arg);
+        } else {
+            positional.push(*arg);
+        }
+    }
+
+    Ok((flags, positional))
+}
+
+fn no_positional(command: &str, positional: &[&str]) -> Result<(), String> {
+    match positional.first() {
+        Some(arg) => Err(format!("{}: unexpected argument '{}'", command, arg)),
+        None => Ok(()),
+    }
+}
+
+pub fn print_usage() {
+    println!("Usage: android-debloater [COMMAND] [OPTIONS]");
+    println!();
+    println!("Runs the interactive menu when no command is given.");
+    println!();
+    println!("Options:");
+    println!("  -s, --serial <serial>            Use this device (defaults to $ANDROID_SERIAL)");
+    println!("  -n, --dry-run                    Print the adb commands instead of changing the device");
+    println!("      --list <file>                Load extra bloatware definitions (repeatable)");
+    println!("      --max-tier <tier>            Riskiest tier that may be removed: recommended (default),");
+    println!("                                   advanced, expert or unsafe");
+    println!("      --user <id|all>              Android user or work profile to work on (repeatable,");
+    println!("                                   defaults to 0, the owner)");
+    println!("      --backup-dir <dir>           Keep backups here (defaults to $ANDROID_DEBLOATER_BACKUP_DIR,");
+    println!("                                   then <data dir>/backups)");
+    println!("      --snapshot-dir <dir>         Keep snapshots here (defaults to $ANDROID_DEBLOATER_SNAPSHOT_DIR,");
+    println!("                                   then <data dir>/snapshots)");
+    println!("      --pull-apks                  Also copy the apks (with splits) into removal backups, so");
+    println!("                                   apps can be reinstalled once the device no longer has them");
+    println!("      --read-labels                Show app names, pulling the apks of listed packages whose");
+    println!("                                   names are not cached yet (slow, transfers every apk once)");
+    println!("      --all-devices                Run remove, restore or apply on every attached device at");
+    println!("                                   once, with a result per device at the end");
+    println!("      --adb-binary                 Run the adb binary for each command instead of talking");
+    println!("                                   to the adb server directly");
+    println!();
+    println!("Commands:");
+    println!("  list [--bloat]                   List system packages (or installed bloatware)");
+    println!("      --third-party                User-installed packages instead");
+    println!("      --disabled | --enabled       Packages in that state");
+    println!("      --uninstalled                Packages already removed for the user");
+    println!("      --all                        Everything, with a state column");
+    println!("                                   Bloat lists are also read from <config dir>/lists/*.json");
+    println!("  search <term>                    Search installed packages");
+    println!("  remove <package>... [options]    Uninstall packages (disables if removal fails)");
+    println!("      --force                      Allow packages of any tier (same as --max-tier unsafe)");
+    println!("      --no-backup                  Skip the automatic backup");
+    println!("  restore <package>...             Reinstall previously removed packages");
+    println!("  restore --backup <file|latest>   Restore every package in a backup");
+    println!("  backup [--bloat]                 Back up the system (or bloatware) package list");
+    println!("  snapshot [file]                  Save the state of every package for every user");
+    println!("  diff <snapshot> [snapshot]       Compare a snapshot with the device (or another snapshot),");
+    println!("                                   'latest' picks the newest; exits 1 when they differ");
+    println!("  revert <snapshot> [--force]      Uninstall, disable, enable and reinstall packages until the");
+    println!("                                   device matches the snapshot again");
+    println!("  apply <profile> [options]        Bring the device in line with a debloat profile, only");
+    println!("                                   changing what differs");
+    println!("      --force                      Ignore the profile's maximum tier");
+    println!("      --no-backup                  Skip the automatic backup");
+    println!("  export <file>                    Save the device's removed and disabled packages as a profile");
+    println!("  devices                          Show connected devices");
+    println!("  users                            Show the device's users and work profiles");
+    println!("  info                             Show device information");
+    println!("  help                             Show this help");
+    println!("  version                          Show version");
+    println!();
+    println!("Exit codes:");
+    println!("  0 success, 1 an operation failed, 2 bad arguments, 3 adb missing, 4 no device,");
+    println!("  5 device unauthorized, 6 device offline, 7 more than one device and none chosen");
+}
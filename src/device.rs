@@ -0,0 +1,11 @@
+// which phone something was taken from, recorded in backups and snapshots
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeviceIdentity {
+    pub serial: String,
+    pub model: String,
+    pub fingerprint: String,
+    pub sdk: String,
+}
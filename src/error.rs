@@ -0,0 +1,163 @@
+// what went wrong talking to a device, worked out from what adb and pm print
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdbError {
+    // the adb binary is not installed, or its server cannot be reached
+    AdbMissing(String),
+    NoDevice,
+    // the selected serial is not attached
+    DeviceNotFound(String),
+    // attached, but the phone has not accepted this computer's key yet
+    Unauthorized(String),
+    Offline(String),
+    // a command got no answer within session::COMMAND_TIMEOUT
+    Timeout(String),
+    // several devices and none picked, with their serials when known
+    MultipleDevices(Vec<String>),
+    // pm refused, with its reason: "DELETE_FAILED_INTERNAL_ERROR", "not installed for 0", ...
+    Package(String),
+    // a shell command exited non-zero for any other reason
+    Command { code: Option<i32>, output: String },
+    // anything else adb reported, or a broken connection
+    Other(String),
+}
+
+impl AdbError {
+    // sorts an adb failure by its message. `code` is the exit code when there is one
+    pub fn from_output(output: &str, code: Option<i32>) -> Self {
+        let text = output.trim();
+        if let Some(reason) = pm_failure(text) {
+            return AdbError::Package(reason);
+        }
+        if text.contains("no devices/emulators found") || text.contains("no devices found") {
+            return AdbError::NoDevice;
+        }
+        if text.contains("more than one device") {
+            return AdbError::MultipleDevices(Vec::new());
+        }
+        if text.contains("device unauthorized") {
+            return AdbError::Unauthorized(String::new());
+        }
+        if text.contains("device offline") {
+            return AdbError::Offline(String::new());
+        }
+        if let Some(serial) = quoted_after(text, "device '").filter(|_| text.contains("not found")) {
+            return AdbError::DeviceNotFound(serial);
+        }
+        match code {
+            Some(_) => AdbError::Command { code, output: text.to_string() },
+            None => AdbError::Other(text.to_string()),
+        }
+    }
+
+    // exit code in scripted mode. 1 is any failed operation and 2 a usage error, so
+    // device problems start at 3
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AdbError::AdbMissing(_) => 3,
+            AdbError::NoDevice | AdbError::DeviceNotFound(_) => 4,
+            AdbError::Unauthorized(_) => 5,
+            AdbError::Offline(_) | AdbError::Timeout(_) => 6,
+            AdbError::MultipleDevices(_) => 7,
+            AdbError::Package(_) | AdbError::Command { .. } | AdbError::Other(_) => 1,
+        }
+    }
+
+    // fills in the serial adb leaves out of "device offline" and the like
+    pub fn on_device(self, serial: &str) -> Self {
+        match self {
+            AdbError::Unauthorized(s) if s.is_empty() => AdbError::Unauthorized(serial.to_string()),
+            AdbError::Offline(s) if s.is_empty() => AdbError::Offline(serial.to_string()),
+            AdbError::Timeout(s) if s.is_empty() => AdbError::Timeout(serial.to_string()),
+            other => other,
+        }
+    }
+
+    // whether the device itself is unreachable, as opposed to one command failing
+    pub fn is_connection(&self) -> bool {
+        self.exit_code() > 2
+    }
+
+    // what the user can do about it, for the connection errors
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            AdbError::AdbMissing(_) => Some("Install the Android platform tools: https://developer.android.com/tools/releases/platform-tools"),
+            AdbError::NoDevice | AdbError::DeviceNotFound(_) => Some("Connect the phone with USB debugging enabled, or use the wireless menu"),
+            AdbError::Unauthorized(_) => Some("Unlock the phone and accept the \"Allow USB debugging?\" prompt"),
+            AdbError::Offline(_) | AdbError::Timeout(_) => Some("Reconnect the cable, or run 'adb reconnect'"),
+            AdbError::MultipleDevices(_) => Some("Choose one with --serial, or use --all-devices"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AdbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let device = |serial: &str| if serial.is_empty() { "device".to_string() } else { format!("device {}", serial) };
+        match self {
+            AdbError::AdbMissing(detail) if detail.is_empty() => write!(f, "adb is not installed or not in PATH"),
+            AdbError::AdbMissing(detail) => write!(f, "adb is not available: {}", detail),
+            AdbError::NoDevice => write!(f, "no device connected"),
+            AdbError::DeviceNotFound(serial) => write!(f, "{} is not attached", device(serial)),
+            AdbError::Unauthorized(serial) => write!(f, "{} has not authorized this computer", device(serial)),
+            AdbError::Offline(serial) => write!(f, "{} is offline", device(serial)),
+            AdbError::Timeout(serial) => write!(f, "{} stopped answering", device(serial)),
+            AdbError::MultipleDevices(serials) if serials.is_empty() => write!(f, "more than one device attached"),
+            AdbError::MultipleDevices(serials) => write!(f, "more than one device attached ({})", serials.join(", ")),
+            AdbError::Package(reason) => write!(f, "{}", describe_pm_reason(reason)),
+            AdbError::Command { code: Some(code), output } if output.is_empty() => write!(f, "command failed with exit code {}", code),
+            AdbError::Command { code: Some(code), output } => write!(f, "{} (exit code {})", output, code),
+            AdbError::Command { code: None, output } | AdbError::Other(output) => write!(f, "{}", output),
+        }
+    }
+}
+
+// "Failure [DELETE_FAILED_INTERNAL_ERROR]" -> "DELETE_FAILED_INTERNAL_ERROR"
+fn pm_failure(text: &str) -> Option<String> {
+    let rest = &text[text.find("Failure [")? + "Failure [".len()..];
+    Some(rest[..rest.find(']')?].to_string())
+}
+
+fn quoted_after(text: &str, prefix: &str) -> Option<String> {
+    let rest = &text[text.find(prefix)? + prefix.len()..];
+    Some(rest[..rest.find('\'')?].to_string())
+}
+
+fn describe_pm_reason(reason: &str) -> String {
+    if let Some(user) = reason.strip_prefix("not installed for ") {
+        return format!("not installed for user {}", user);
+    }
+    let meaning = match reason {
+        "DELETE_FAILED_INTERNAL_ERROR" => "the system refused to remove it",
+        "DELETE_FAILED_DEVICE_POLICY_MANAGER" => "it is a device admin app, deactivate it first",
+        "DELETE_FAILED_USER_RESTRICTED" => "removing apps is restricted for this user",
+        "DELETE_FAILED_OWNER_BLOCKED" => "the device owner blocks removing it",
+        "DELETE_FAILED_ABORTED" => "the removal was cancelled",
+        _ => return format!("pm: {}", reason),
+    };
+    format!("{} ({})", meaning, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_adb_and_pm_output() {
+        assert_eq!(AdbError::from_output("Failure [DELETE_FAILED_INTERNAL_ERROR]\n", Some(1)), AdbError::Package("DELETE_FAILED_INTERNAL_ERROR".to_string()));
+        assert_eq!(AdbError::from_output("Failure [not installed for 0]", Some(1)).to_string(), "not installed for user 0");
+        assert_eq!(AdbError::from_output("adb: no devices/emulators found", Some(1)), AdbError::NoDevice);
+        assert_eq!(AdbError::from_output("error: device unauthorized.\nThis adb server's $ADB_VENDOR_KEYS is not set", Some(1)).exit_code(), 5);
+        assert_eq!(AdbError::from_output("adb: device offline", Some(1)), AdbError::Offline(String::new()));
+        assert_eq!(AdbError::from_output("adb: more than one device/emulator", Some(1)).exit_code(), 7);
+        assert_eq!(AdbError::from_output("adb: device 'R58M123ABC' not found", Some(1)), AdbError::DeviceNotFound("R58M123ABC".to_string()));
+
+        let failed = AdbError::from_output("Error: java.lang.IllegalArgumentException: Unknown package: com.x\n", Some(255));
+        assert_eq!(failed.exit_code(), 1);
+        assert!(!failed.is_connection());
+        assert_eq!(failed.to_string(), "Error: java.lang.IllegalArgumentException: Unknown package: com.x (exit code 255)");
+        assert_eq!(AdbError::Unauthorized("R58M123ABC".to_string()).to_string(), "device R58M123ABC has not authorized this computer");
+    }
+}
@@ -0,0 +1,406 @@
+// in-memory stand-in for a phone, understands the pm/getprop commands we send
+
+use crate::backend::{DeviceBackend, DeviceEntry};
+use crate::error::AdbError;
+use crate::session::ShellSession;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FakePackage {
+    pub system: bool,
+    pub installed: bool,
+    pub enabled: bool,
+}
+
+pub struct FakeDevice {
+    pub serial: String,
+    // as `adb devices` shows it: "device", "unauthorized", "offline"
+    pub status: String,
+    // shared with open sessions
+    state: Arc<Mutex<FakeState>>,
+}
+
+#[derive(Default)]
+struct FakeState {
+    // user id -> that user's packages; user 0 always exists
+    users: BTreeMap<u32, FakeUser>,
+    props: HashMap<String, String>,
+    // files `pull` can copy, by device path
+    files: HashMap<String, Vec<u8>>,
+    // package -> its apk paths in `files`, base first
+    apks: BTreeMap<String, Vec<String>>,
+    // shell commands starting with the prefix fail, printing the output when there is one
+    failures: Vec<(String, Option<String>)>,
+    // session commands starting with one of these never answer
+    hangs: Vec<String>,
+    // every shell command run, joined with spaces
+    log: Vec<String>,
+    // how many shell sessions were opened
+    sessions: usize,
+}
+
+#[derive(Default)]
+struct FakeUser {
+    name: String,
+    packages: BTreeMap<String, FakePackage>,
+}
+
+impl FakeDevice {
+    pub fn new(serial: &str) -> Self {
+        let mut state = FakeState::default();
+        state.users.insert(0, FakeUser {
+            name: "Owner".to_string(),
+            packages: BTreeMap::new(),
+        });
+
+        FakeDevice {
+            serial: serial.to_string(),
+            status: "device".to_string(),
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    pub fn with_status(mut self, status: &str) -> Self {
+        self.status = status.to_string();
+        self
+    }
+
+    pub fn with_system_package(self, name: &str) -> Self {
+        self.add_package(name, true);
+        self
+    }
+
+    pub fn with_user_package(self, name: &str) -> Self {
+        self.add_package(name, false);
+        self
+    }
+
+    // a secondary user or profile with a copy of the owner's current packages
+    pub fn with_profile(self, id: u32, name: &str) -> Self {
+        {
+            let mut state = self.state.lock().unwrap();
+            let packages = state.users[&0].packages.clone();
+            state.users.insert(id, FakeUser {
+                name: name.to_string(),
+                packages,
+            });
+        }
+        self
+    }
+
+    pub fn with_prop(self, key: &str, value: &str) -> Self {
+        self.state.lock().unwrap().props.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn with_file(self, path: &str, content: Vec<u8>) -> Self {
+        self.state.lock().unwrap().files.insert(path.to_string(), content);
+        self
+    }
+
+    // apks `pm path` reports for the package, `install_multiple` accepts them back
+    pub fn with_apks(self, package: &str, apks: &[(&str, &[u8])]) -> Self {
+        {
+            let mut state = self.state.lock().unwrap();
+            for (path, content) in apks {
+                state.files.insert(path.to_string(), content.to_vec());
+            }
+            state.apks.insert(package.to_string(), apks.iter().map(|(p, _)| p.to_string()).collect());
+        }
+        self
+    }
+
+    // make every shell command starting with `prefix` fail
+    pub fn fail_on(self, prefix: &str) -> Self {
+        self.state.lock().unwrap().failures.push((prefix.to_string(), None));
+        self
+    }
+
+    // make session commands starting with `prefix` time out, like a hung pm
+    pub fn hang_on(self, prefix: &str) -> Self {
+        self.state.lock().unwrap().hangs.push(prefix.to_string());
+        self
+    }
+
+    // like fail_on, with what adb prints: "error: device offline", ...
+    pub fn fail_with(self, prefix: &str, output: &str) -> Self {
+        self.state.lock().unwrap().failures.push((prefix.to_string(), Some(output.to_string())));
+        self
+    }
+
+    // installs the package for the owner
+    pub fn add_package(&self, name: &str, system: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.users.get_mut(&0).unwrap().packages.insert(name.to_string(), FakePackage {
+            system,
+            installed: true,
+            enabled: true,
+        });
+    }
+
+    // the package as the owner sees it
+    pub fn package(&self, name: &str) -> Option<FakePackage> {
+        self.package_for(0, name)
+    }
+
+    pub fn package_for(&self, user: u32, name: &str) -> Option<FakePackage> {
+        let state = self.state.lock().unwrap();
+        state.users.get(&user).and_then(|u| u.packages.get(name).cloned())
+    }
+
+    pub fn commands(&self) -> Vec<String> {
+        self.state.lock().unwrap().log.clone()
+    }
+
+    pub fn sessions(&self) -> usize {
+        self.state.lock().unwrap().sessions
+    }
+}
+
+impl FakeState {
+    // a shell command as adb runs it: logged, then failed on request or run
+    fn shell(&mut self, args: &[&str]) -> Result<String, String> {
+        let command = args.join(" ");
+        self.log.push(command.clone());
+
+        if let Some((_, output)) = self.failures.iter().find(|(f, _)| command.starts_with(f.as_str())) {
+            return Err(output.clone().unwrap_or_else(|| format!("fake device: injected failure: {}\n", command)));
+        }
+
+        self.run(args)
+    }
+
+    fn run(&mut self, args: &[&str]) -> Result<String, String> {
+        // `--user N` can appear anywhere, everything else is matched without it
+        let mut user = 0;
+        let mut rest = Vec::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if *arg == "--user" {
+                user = iter.next()
+                    .and_then(|u| u.parse().ok())
+                    .ok_or("fake device: bad --user\n")?;
+            } else {
+                rest.push(*arg);
+            }
+        }
+
+        if let ["pm", "list", "users"] = rest.as_slice() {
+            return Ok(self.list_users());
+        }
+        if let ["getprop", key] = rest.as_slice() {
+            return Ok(format!("{}\n", self.props.get(*key).cloned().unwrap_or_default()));
+        }
+
+        let packages = match self.users.get_mut(&user) {
+            Some(u) => &mut u.packages,
+            None => return Err(format!("Error: user {} does not exist\n", user)),
+        };
+
+        match rest.as_slice() {
+            ["pm", "list", "packages", flags @ ..] => Ok(list_packages(packages, flags)),
+            // like the real one, prints nothing and fails for unknown packages
+            ["pm", "path", name] => match packages.get(*name) {
+                Some(pkg) if pkg.installed => Ok(match self.apks.get(*name) {
+                    Some(paths) => paths.iter().map(|p| format!("package:{}\n", p)).collect(),
+                    None if pkg.system => format!("package:/system/app/{}/{}.apk\n", name, name),
+                    None => format!("package:/data/app/~~x==/{}-y==/base.apk\n", name),
+                }),
+                _ => Err(String::new()),
+            },
+            ["pm", "uninstall", name] => match packages.get_mut(*name) {
+                Some(pkg) if pkg.installed => {
+                    if pkg.system {
+                        pkg.installed = false;
+                    } else {
+                        packages.remove(*name);
+                    }
+                    Ok("Success\n".to_string())
+                }
+                _ => Err(format!("Failure [not installed for {}]\n", user)),
+            },
+            ["pm", "disable-user", name] => match packages.get_mut(*name) {
+                Some(pkg) if pkg.installed => {
+                    pkg.enabled = false;
+                    Ok(format!("Package {} new state: disabled-user\n", name))
+                }
+                _ => Err(format!("Unknown package: {}\n", name)),
+            },
+            ["pm", "enable", name] => match packages.get_mut(*name) {
+                Some(pkg) if pkg.installed => {
+                    pkg.enabled = true;
+                    Ok(format!("Package {} new state: enabled\n", name))
+                }
+                _ => Err(format!("Unknown package: {}\n", name)),
+            },
+            ["cmd", "package", "install-existing", name] => match packages.get_mut(*name) {
+                Some(pkg) => {
+                    pkg.installed = true;
+                    Ok(format!("Package {} installed for user: {}\n", name, user))
+                }
+                None => Err(format!("Package {} doesn't exist\n", name)),
+            },
+            _ => Err(format!("fake device: unsupported command: {}\n", args.join(" "))),
+        }
+    }
+
+    fn list_users(&self) -> String {
+        let mut out = "Users:\n".to_string();
+        for (id, user) in &self.users {
+            out.push_str(&format!("\tUserInfo{{{}:{}:c13}} running\n", id, user.name));
+        }
+        out
+    }
+}
+
+fn list_packages(packages: &BTreeMap<String, FakePackage>, args: &[&str]) -> String {
+    let mut system_only = false;
+    let mut third_party_only = false;
+    let mut disabled_only = false;
+    let mut enabled_only = false;
+    let mut include_uninstalled = false;
+    let mut details = false;
+    let mut filter = None;
+
+    for arg in args {
+        match *arg {
+            "-s" => system_only = true,
+            "-3" => third_party_only = true,
+            "-d" => disabled_only = true,
+            "-e" => enabled_only = true,
+            "-u" => include_uninstalled = true,
+            // always given together by the snapshot
+            "-f" | "-i" | "-U" | "--show-versioncode" => details = true,
+            other => filter = Some(other),
+        }
+    }
+
+    let mut out = String::new();
+    for (i, (name, pkg)) in packages.iter().enumerate() {
+        if (!pkg.installed && !include_uninstalled)
+            || (system_only && !pkg.system)
+            || (third_party_only && pkg.system)
+            || (disabled_only && pkg.enabled)
+            || (enabled_only && !pkg.enabled)
+            || filter.is_some_and(|f| !name.contains(f))
+        {
+            continue;
+        }
+        if details {
+            let (path, installer) = if pkg.system {
+                (format!("/system/app/{}/{}.apk", name, name), "null")
+            } else {
+                (format!("/data/app/~~x==/{}-y==/base.apk", name), "com.android.vending")
+            };
+            out.push_str(&format!("package:{}={} versionCode:1  installer={} uid:{}\n",
+                path, name, installer, 10000 + i));
+        } else {
+            out.push_str(&format!("package:{}\n", name));
+        }
+    }
+    out
+}
+
+impl DeviceBackend for FakeDevice {
+    fn devices(&self) -> Result<Vec<DeviceEntry>, AdbError> {
+        Ok(vec![DeviceEntry {
+            serial: self.serial.clone(),
+            state: self.status.clone(),
+            details: "model:Fake_Phone".to_string(),
+        }])
+    }
+
+    fn shell(&self, serial: Option<&str>, args: &[&str]) -> Result<String, AdbError> {
+        if serial.is_some_and(|s| s != self.serial) {
+            return Err(AdbError::DeviceNotFound(serial.unwrap_or_default().to_string()));
+        }
+
+        self.state.lock().unwrap().shell(args).map_err(|output| AdbError::from_output(&output, Some(1)))
+    }
+
+    fn pair(&self, _address: &str, _code: &str) -> Result<String, AdbError> {
+        Ok("Successfully paired\n".to_string())
+    }
+
+    fn connect(&self, address: &str) -> Result<String, AdbError> {
+        Ok(format!("connected to {}\n", address))
+    }
+
+    fn disconnect(&self, address: &str) -> Result<String, AdbError> {
+        Ok(format!("disconnected {}\n", address))
+    }
+
+    fn tcpip(&self, _serial: Option<&str>, port: u16) -> Result<String, AdbError> {
+        Ok(format!("restarting in TCP mode port: {}\n", port))
+    }
+
+    fn pull(&self, _serial: Option<&str>, remote: &str, local: &Path) -> Result<String, AdbError> {
+        let state = self.state.lock().unwrap();
+        match state.files.get(remote) {
+            Some(content) => {
+                fs::write(local, content).map_err(|e| AdbError::Other(e.to_string()))?;
+                Ok(format!("{}: 1 file pulled\n", remote))
+            }
+            None => Err(AdbError::Other(format!("adb: error: remote object '{}' does not exist", remote))),
+        }
+    }
+
+    fn install_multiple(&self, _serial: Option<&str>, apks: &[&Path], user: u32) -> Result<String, AdbError> {
+        let mut state = self.state.lock().unwrap();
+        let names: Vec<String> = apks.iter().map(|p| p.display().to_string()).collect();
+        state.log.push(format!("install-multiple --user {} {}", user, names.join(" ")));
+
+        // the package whose registered base apk has the same content
+        let invalid = || AdbError::Package("INSTALL_FAILED_INVALID_APK".to_string());
+        let base = apks.first().and_then(|p| fs::read(p).ok()).ok_or_else(invalid)?;
+        let package = state.apks.iter()
+            .find(|(_, paths)| paths.first().and_then(|p| state.files.get(p)) == Some(&base))
+            .map(|(name, _)| name.clone())
+            .ok_or_else(invalid)?;
+
+        let packages = match state.users.get_mut(&user) {
+            Some(u) => &mut u.packages,
+            None => return Err(AdbError::Package(format!("user {} does not exist", user))),
+        };
+        let pkg = packages.entry(package).or_insert(FakePackage {
+            system: false,
+            installed: true,
+            enabled: true,
+        });
+        pkg.installed = true;
+        pkg.enabled = true;
+        Ok("Success\n".to_string())
+    }
+    fn open_session(&self, serial: Option<&str>) -> Result<Box<dyn ShellSession>, AdbError> {
+        if serial.is_some_and(|s| s != self.serial) {
+            return Err(AdbError::DeviceNotFound(serial.unwrap_or_default().to_string()));
+        }
+        let mut state = self.state.lock().unwrap();
+        state.sessions += 1;
+        Ok(Box::new(FakeSession { state: self.state.clone() }))
+    }
+}
+
+// commands go to the same state as `shell`, exit code 1 standing in for any failure
+struct FakeSession {
+    state: Arc<Mutex<FakeState>>,
+}
+
+impl ShellSession for FakeSession {
+    fn run(&mut self, args: &[&str]) -> io::Result<(String, i32)> {
+        let mut state = self.state.lock().unwrap();
+        let command = args.join(" ");
+        if state.hangs.iter().any(|h| command.starts_with(h.as_str())) {
+            state.log.push(command);
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "no answer from the device"));
+        }
+        Ok(match state.shell(args) {
+            Ok(output) => (output, 0),
+            Err(output) => (output, 1),
+        })
+    }
+}
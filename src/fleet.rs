@@ -0,0 +1,146 @@
+// fleet mode: runs one command on every attached device at once. each device gets its own
+// copy of this program pinned with -s, so their output and state never mix
+
+use crate::backend::DeviceEntry;
+use colored::*;
+use std::env;
+use std::io::{BufRead, BufReader, IsTerminal, Read};
+use std::process::{Command, Stdio};
+use std::thread;
+
+// set for the per-device copies, so work that is done once per run stays in the parent
+const CHILD_ENV: &str = "ANDROID_DEBLOATER_FLEET_CHILD";
+
+pub fn is_child() -> bool {
+    env::var_os(CHILD_ENV).is_some()
+}
+
+pub struct DeviceRun {
+    pub serial: String,
+    pub model: String,
+    // exit code, or why the run could not start
+    pub result: Result<i32, String>,
+}
+
+impl DeviceRun {
+    pub fn succeeded(&self) -> bool {
+        matches!(self.result, Ok(0))
+    }
+}
+
+// the arguments for one device: everything but --all-devices and any serial
+pub fn device_args(args: &[String], serial: &str) -> Vec<String> {
+    let mut out = vec!["-s".to_string(), serial.to_string()];
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "-s" || arg == "--serial" {
+            iter.next();
+        } else if arg != "--all-devices" && !arg.starts_with("--serial=") {
+            out.push(arg.clone());
+        }
+    }
+    out
+}
+
+// runs the command on every device in parallel, printing their output as it comes
+pub fn run(devices: &[DeviceEntry], args: &[String]) -> Vec<DeviceRun> {
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            return devices.iter().map(|d| DeviceRun {
+                serial: d.serial.clone(),
+                model: d.model().unwrap_or_default().to_string(),
+                result: Err(format!("cannot find own executable: {}", e)),
+            }).collect();
+        }
+    };
+    let width = devices.iter().map(|d| d.serial.len()).max().unwrap_or(0);
+    let color = std::io::stdout().is_terminal();
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = devices.iter().map(|device| {
+            let exe = &exe;
+            scope.spawn(move || {
+                let tag = format!("[{:<width$}]", device.serial, width = width).bright_black().to_string();
+                let mut command = Command::new(exe);
+                command.args(device_args(args, &device.serial))
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .env(CHILD_ENV, "1");
+                if color {
+                    command.env("CLICOLOR_FORCE", "1");
+                }
+
+                let result = command.spawn()
+                    .map_err(|e| e.to_string())
+                    .and_then(|mut child| {
+                        let stdout = child.stdout.take();
+                        let stderr = child.stderr.take();
+                        thread::scope(|lines| {
+                            if let Some(stderr) = stderr {
+                                lines.spawn(|| print_prefixed(&tag, stderr));
+                            }
+                            if let Some(stdout) = stdout {
+                                print_prefixed(&tag, stdout);
+                            }
+                        });
+                        child.wait().map_err(|e| e.to_string())
+                    })
+                    .map(|status| status.code().unwrap_or(1));
+
+                DeviceRun {
+                    serial: device.serial.clone(),
+                    model: device.model().unwrap_or_default().to_string(),
+                    result,
+                }
+            })
+        }).collect();
+
+        handles.into_iter().map(|h| h.join().expect("fleet thread panicked")).collect()
+    })
+}
+
+fn print_prefixed(tag: &str, output: impl Read) {
+    for line in BufReader::new(output).lines().map_while(Result::ok) {
+        if !line.trim().is_empty() {
+            println!("{} {}", tag, line);
+        }
+    }
+}
+
+pub fn print_matrix(runs: &[DeviceRun]) {
+    let width = runs.iter().map(|r| r.serial.len()).max().unwrap_or(0).max("Device".len());
+    let model_width = runs.iter().map(|r| r.model.len()).max().unwrap_or(0).max("Model".len());
+
+    println!();
+    println!("{}", "=========================================".cyan());
+    println!("{}", format!("{:<width$}  {:<model_width$}  Result", "Device", "Model").cyan().bold());
+    for run in runs {
+        let result = match &run.result {
+            Ok(0) => "OK".green().bold(),
+            Ok(code) => format!("FAILED (exit {})", code).red().bold(),
+            Err(e) => format!("ERROR ({})", e).red().bold(),
+        };
+        println!("{}  {:<model_width$}  {}", format!("{:<width$}", run.serial).bright_white(), run.model, result);
+    }
+    println!("{}", "=========================================".cyan());
+
+    let ok = runs.iter().filter(|r| r.succeeded()).count();
+    println!("{} {} of {} device(s)", "Succeeded:".cyan(), ok.to_string().bright_white(), runs.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_args_pin_the_serial() {
+        let args: Vec<String> = ["--all-devices", "-s", "old", "remove", "--serial=x", "--dry-run", "com.facebook.katana"]
+            .iter().map(|s| s.to_string()).collect();
+        assert_eq!(device_args(&args, "R58M123ABC"), ["-s", "R58M123ABC", "remove", "--dry-run", "com.facebook.katana"]);
+
+        let run = DeviceRun { serial: "a".to_string(), model: String::new(), result: Ok(1) };
+        assert!(!run.succeeded());
+    }
+}
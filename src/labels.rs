@@ -0,0 +1,60 @@
+// app labels read from pulled apks. pulling is slow, so they are cached per
+// build fingerprint; an empty label means the apk was read and had none
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct LabelCache {
+    // None when there is nowhere to keep it (no cache dir or no fingerprint)
+    path: Option<PathBuf>,
+    labels: BTreeMap<String, String>,
+    changed: bool,
+}
+
+impl LabelCache {
+    pub fn load(dir: Option<&Path>, fingerprint: &str) -> Self {
+        let path = match dir {
+            Some(dir) if !fingerprint.is_empty() => Some(dir.join(file_name(fingerprint))),
+            _ => None,
+        };
+
+        let labels = path.as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        LabelCache { path, labels, changed: false }
+    }
+
+    // Some("") when the package is known to have no label
+    pub fn get(&self, package: &str) -> Option<&str> {
+        self.labels.get(package).map(String::as_str)
+    }
+
+    pub fn insert(&mut self, package: &str, label: String) {
+        self.labels.insert(package.to_string(), label);
+        self.changed = true;
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = match &self.path {
+            Some(path) if self.changed => path,
+            _ => return Ok(()),
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(&self.labels).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+// fingerprints look like samsung/beyond1ltexx/beyond1:12/SP1A.210812.016/G973FXXSGHWC1:user/release-keys
+fn file_name(fingerprint: &str) -> String {
+    let safe: String = fingerprint.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect();
+    format!("{}.json", safe)
+}
@@ -1,6 +1,7 @@
 use colored::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
 use std::io::{self, Write};
 use std::process::{Command, Stdio};
@@ -8,6 +9,44 @@ use std::thread;
 use std::time::Duration;
 use std::path::PathBuf;
 use std::env;
+use std::path::Path;
+use std::process;
+use std::rc::Rc;
+use std::sync::Arc;
+
+mod adb_client;
+mod apk;
+mod apk_backup;
+mod backend;
+mod bloatlist;
+mod cli;
+mod device;
+mod error;
+#[cfg(test)]
+mod fake;
+mod fleet;
+mod labels;
+mod packages;
+mod paths;
+mod profile;
+mod session;
+mod snapshot;
+mod uad;
+mod users;
+
+use adb_client::AdbClient;
+use apk_backup::ApkFile;
+use backend::{AdbBackend, DeviceBackend, DeviceEntry};
+use bloatlist::{BloatList, Removal};
+use cli::{CliCommand, Options};
+use device::DeviceIdentity;
+use error::AdbError;
+use labels::LabelCache;
+use packages::{ListMode, PackageState};
+use profile::{Desired, Profile, ProfileEntry, ProfileUsers};
+use session::ShellSession;
+use snapshot::DeviceSnapshot;
+use users::DeviceUser;
 
 #[derive(Clone, Debug, PartialEq)]
 enum ConnectionType {
@@ -23,65 +62,398 @@ struct Package {
     is_system: bool,
     #[serde(skip)]
     is_selected: bool,
+    // app name as shown in the launcher, read from the apk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    // base apk, empty when pm did not tell us
+    #[serde(default)]
+    apk_path: String,
+    #[serde(default)]
+    version_code: Option<u64>,
+    // who installed it, None for preinstalled packages
+    #[serde(default)]
+    installer: Option<String>,
+    #[serde(default)]
+    uid: Option<u32>,
+    // for the current user
+    #[serde(default = "default_true")]
+    installed: bool,
+    #[serde(default = "default_true")]
+    enabled: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Package {
+    fn new(name: &str) -> Self {
+        Package {
+            name: name.to_string(),
+            is_system: false,
+            is_selected: false,
+            label: None,
+            apk_path: String::new(),
+            version_code: None,
+            installer: None,
+            uid: None,
+            installed: true,
+            enabled: true,
+        }
+    }
+
+    // parses a line of `pm list packages -f -i -U --show-versioncode`:
+    // package:/data/app/~~a==/com.foo-b==/base.apk=com.foo versionCode:12  installer=com.android.vending uid:10123
+    fn parse_listing(line: &str) -> Option<Package> {
+        let mut fields = line.trim().strip_prefix("package:")?.split_whitespace();
+        // apk paths can contain '=' themselves, the name comes after the last one
+        let mut package = match fields.next()?.rsplit_once('=') {
+            Some((path, name)) => {
+                let mut package = Package::new(name);
+                package.apk_path = path.to_string();
+                package
+            }
+            None => return None,
+        };
+
+        for field in fields {
+            if let Some(code) = field.strip_prefix("versionCode:") {
+                package.version_code = code.parse().ok();
+            } else if let Some(installer) = field.strip_prefix("installer=") {
+                if installer != "null" {
+                    package.installer = Some(installer.to_string());
+                }
+            } else if let Some(uid) = field.strip_prefix("uid:") {
+                package.uid = uid.parse().ok();
+            }
+        }
+
+        Some(package)
+    }
+
+    // case-insensitive match on the package name or the label
+    fn matches(&self, term: &str) -> bool {
+        let term = term.to_lowercase();
+        self.name.to_lowercase().contains(&term)
+            || self.label.as_ref().is_some_and(|l| l.to_lowercase().contains(&term))
+    }
+
+    fn state(&self) -> PackageState {
+        PackageState { installed: self.installed, enabled: self.enabled }
+    }
+
+    // None for the usual installed and enabled
+    fn state_label(&self) -> Option<&'static str> {
+        if !self.installed {
+            Some("uninstalled")
+        } else if !self.enabled {
+            Some("disabled")
+        } else {
+            None
+        }
+    }
+}
+
+// one listing of the device's packages, reused until something is removed or restored
+struct PackageSnapshot {
+    // device the listing came from
+    serial: String,
+    // every package the device knows, including ones uninstalled for the user
+    packages: BTreeMap<String, Package>,
+}
+
+impl PackageSnapshot {
+    fn contains(&self, package_name: &str) -> bool {
+        self.packages.get(package_name).is_some_and(|p| p.installed)
+    }
+
+    fn installed(&self) -> impl Iterator<Item = &Package> {
+        self.packages.values().filter(|p| p.installed)
+    }
 }
 
 struct AppState {
     packages: Vec<Package>,
     connection: ConnectionType,
     connected_device: String,
+    // device picked by the user or --serial, None means "the only one attached"
+    serial: Option<String>,
+    // whether we may prompt (device picker etc.)
+    interactive: bool,
+    backend: Arc<dyn DeviceBackend>,
+    backup_dir: PathBuf,
+    // print destructive commands instead of running them
+    dry_run: bool,
+    bloat_list: BloatList,
+    // riskiest removal tier allowed without an extra confirmation
+    max_tier: Removal,
+    // users removals, restores and listings apply to
+    users: Vec<u32>,
+    // every user on the device, looked up when needed
+    all_users: bool,
+    // per user
+    snapshots: RefCell<BTreeMap<u32, Rc<PackageSnapshot>>>,
+    // where app labels are cached, None to not cache them
+    label_dir: Option<PathBuf>,
+    // removal backups also keep the apks
+    pull_apks: bool,
+    // show app labels, pulling apks for the ones missing from the cache
+    read_labels: bool,
+    // where full device snapshots are saved
+    // None when there is no data dir and no --snapshot-dir
+    snapshot_dir: Option<PathBuf>,
+    // one shell kept open while a batch runs, see start_session
+    session: RefCell<Option<Box<dyn ShellSession>>>,
+    // why the device cannot be used: the last check_device_connected failure, or a
+    // command that lost the connection since
+    device_error: RefCell<Option<AdbError>>,
 }
 
+// bumped whenever the backup layout changes. the original {timestamp, packages} files
+// have none and count as version 1
+const BACKUP_VERSION: u32 = 2;
+
 #[derive(Serialize, Deserialize)]
 struct Backup {
+    #[serde(default = "legacy_backup_version")]
+    version: u32,
     timestamp: String,
+    // the phone the backup was taken from, missing in version 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    device: Option<DeviceIdentity>,
+    // kept for older versions of the tool, `entries` is what restores read
     packages: Vec<String>,
+    #[serde(default = "default_users")]
+    users: Vec<u32>,
+    // one per user and package
+    #[serde(default)]
+    entries: Vec<BackupEntry>,
+    // apks copied next to the backup, per package, see apk_dir
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    apks: BTreeMap<String, Vec<ApkFile>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct BackupEntry {
+    package: String,
+    user: u32,
+    // state before the removal, None when unknown
+    #[serde(default)]
+    prior: Option<PackageState>,
+    // what the removal did, None until it ran
+    #[serde(default)]
+    action: Option<RemovalOutcome>,
+}
+
+fn legacy_backup_version() -> u32 {
+    1
+}
+
+fn default_users() -> Vec<u32> {
+    vec![0]
+}
+
+impl Backup {
+    fn new(packages: Vec<String>, users: Vec<u32>) -> Self {
+        let entries = users.iter()
+            .flat_map(|u| packages.iter().map(move |p| BackupEntry {
+                package: p.clone(),
+                user: *u,
+                prior: None,
+                action: None,
+            }))
+            .collect();
+
+        Backup {
+            version: BACKUP_VERSION,
+            timestamp: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            device: None,
+            packages,
+            users,
+            entries,
+            apks: BTreeMap::new(),
+        }
+    }
+
+    // a backup of the packages as the device has them right now
+    fn capture(state: &AppState, packages: Vec<String>) -> Self {
+        let mut backup = Backup::new(packages, state.users());
+        backup.device = Some(device_identity(state));
+
+        for entry in &mut backup.entries {
+            if let Ok(snapshot) = state.user_packages(entry.user) {
+                entry.prior = Some(match snapshot.packages.get(&entry.package) {
+                    Some(pkg) => pkg.state(),
+                    None => PackageState { installed: false, enabled: false },
+                });
+            }
+        }
+        backup
+    }
+
+    #[cfg(test)]
+    fn entry(&self, user: u32, package: &str) -> Option<&BackupEntry> {
+        self.entries.iter().find(|e| e.user == user && e.package == package)
+    }
+
+    #[cfg(test)]
+    fn outcome(&self, user: u32, package: &str) -> Option<&RemovalOutcome> {
+        self.entry(user, package).and_then(|e| e.action.as_ref())
+    }
+}
+
+fn read_backup(path: &Path) -> Result<Backup, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read backup: {}", e))?;
+    let mut backup: Backup = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse backup: {}", e))?;
+
+    if backup.version > BACKUP_VERSION {
+        return Err(format!("Backup format version {} is newer than this tool supports ({})", backup.version, BACKUP_VERSION));
+    }
+
+    // version 1 is only the package names, removed for the owner with no record of how
+    if backup.version < 2 {
+        backup.entries = backup.packages.iter()
+            .map(|package| BackupEntry {
+                package: package.clone(),
+                user: 0,
+                prior: None,
+                action: None,
+            })
+            .collect();
+    }
+    Ok(backup)
+}
+
+// serial, model, build and sdk of the connected device, empty where a prop is missing
+fn device_identity(state: &AppState) -> DeviceIdentity {
+    let prop = |key: &str| state.shell(&["getprop", key])
+        .map(|out| out.trim().to_string())
+        .unwrap_or_default();
+
+    let mut serial = prop("ro.serialno");
+    if serial.is_empty() {
+        serial = state.connected_device.clone();
+    }
+
+    DeviceIdentity {
+        serial,
+        model: prop("ro.product.model"),
+        fingerprint: prop("ro.build.fingerprint"),
+        sdk: prop("ro.build.version.sdk"),
+    }
+}
+
+// reasons the backup may not fit the connected device
+fn identity_mismatches(backup: &DeviceIdentity, current: &DeviceIdentity) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    let differs = |a: &str, b: &str| !a.is_empty() && !b.is_empty() && a != b;
+
+    if differs(&backup.serial, &current.serial) {
+        mismatches.push(format!("taken from device {} ({}), connected is {} ({})",
+            backup.serial, backup.model, current.serial, current.model));
+    }
+    if differs(&backup.fingerprint, &current.fingerprint) {
+        mismatches.push(format!("taken on build {}, device now runs {}", backup.fingerprint, current.fingerprint));
+    } else if differs(&backup.sdk, &current.sdk) {
+        mismatches.push(format!("taken on SDK {}, device now runs SDK {}", backup.sdk, current.sdk));
+    }
+    mismatches
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RemovalOutcome {
+    Uninstalled,
+    // uninstall failed but `pm disable-user` worked
+    Disabled,
+    Failed(String),
+    // dry run, nothing was done
+    Skipped,
+}
+
+impl RemovalOutcome {
+    fn is_success(&self) -> bool {
+        !matches!(self, RemovalOutcome::Failed(_))
+    }
+}
+
+struct RemovalResult {
+    package: String,
+    user: u32,
+    outcome: RemovalOutcome,
 }
 
-const CRITICAL_PACKAGES: &[&str] = &[
-    "com.android.systemui",
-    "com.android.settings",
-    "com.android.phone",
-    "com.android.providers.settings",
-    "com.android.providers.contacts",
-    "com.android.vending",
-    "com.google.android.gms",
-    "com.android.inputmethod.latin",
-    "com.android.launcher3",
-];
-
-const COMMON_BLOATWARE: &[&str] = &[
-    "com.facebook.katana",
-    "com.facebook.system",
-    "com.facebook.appmanager",
-    "com.facebook.services",
-    "com.netflix.mediaclient",
-    "com.spotify.music",
-    "com.linkedin.android",
-    "com.microsoft.office.excel",
-    "com.microsoft.office.word",
-    "com.microsoft.office.powerpoint",
-    "com.microsoft.skype.raider",
-    "com.android.bips",
-    "com.android.bookmarkprovider",
-    "com.android.dreams.basic",
-    "com.android.dreams.phototable",
-    "com.android.egg",
-    "com.android.printspooler",
-    "com.google.android.apps.docs",
-    "com.google.android.apps.maps",
-    "com.google.android.apps.photos",
-    "com.google.android.apps.tachyon",
-    "com.google.android.music",
-    "com.google.android.videos",
-    "com.google.android.youtube",
-    "com.samsung.android.game.gamehome",
-    "com.samsung.android.game.gametools",
-    "com.samsung.android.bixby.agent",
-    "com.samsung.android.app.spage",
-    "com.samsung.android.messaging",
-];
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RestoreAction {
+    // already installed and enabled
+    Nothing,
+    Enable,
+    InstallExisting,
+    InstallAndEnable,
+    // the device lost it, installed again from the apks in a backup
+    InstallApks,
+}
+
+impl RestoreAction {
+    // what brings `current` back to `target`, never disables or uninstalls
+    fn towards(current: PackageState, target: PackageState) -> Self {
+        let install = target.installed && !current.installed;
+        let enable = target.enabled && !current.enabled;
+        match (install, enable) {
+            (false, false) => RestoreAction::Nothing,
+            (false, true) => RestoreAction::Enable,
+            (true, false) => RestoreAction::InstallExisting,
+            (true, true) => RestoreAction::InstallAndEnable,
+        }
+    }
+
+    fn commands<'a>(self, package: &'a str, user: &'a str) -> Vec<Vec<&'a str>> {
+        let install = vec!["cmd", "package", "install-existing", "--user", user, package];
+        let enable = vec!["pm", "enable", "--user", user, package];
+        match self {
+            RestoreAction::Nothing => Vec::new(),
+            RestoreAction::Enable => vec![enable],
+            RestoreAction::InstallExisting => vec![install],
+            RestoreAction::InstallAndEnable => vec![install, enable],
+            RestoreAction::InstallApks => Vec::new(),
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            RestoreAction::Nothing => "nothing to restore",
+            RestoreAction::Enable => "re-enabled",
+            RestoreAction::InstallExisting => "reinstalled",
+            RestoreAction::InstallAndEnable => "reinstalled and re-enabled",
+            RestoreAction::InstallApks => "reinstalled from the backed up apks",
+        }
+    }
+}
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let cli = match cli::parse_args(&args) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{} {}", "Error:".red().bold(), e);
+            eprintln!("Run 'android-debloater help' for usage");
+            process::exit(2);
+        }
+    };
+
+    match cli.command {
+        CliCommand::Interactive => run_interactive(cli.options),
+        CliCommand::Help => cli::print_usage(),
+        CliCommand::Version => println!("android-debloater {}", env!("CARGO_PKG_VERSION")),
+        command if cli.options.all_devices => process::exit(run_fleet(&command, &cli.options, &args)),
+        command => process::exit(run_command(command, cli.options)),
+    }
+}
+
+fn run_interactive(options: Options) {
     print_header();
 
     if !check_adb() {
@@ -104,7 +476,7 @@ fn main() {
 
     println!("{}", "ADB found".green());
 
-    let mut state = AppState::new();
+    let mut state = AppState::from_options(&options, true);
 
     loop {
         display_main_menu(&state);
@@ -112,18 +484,56 @@ fn main() {
         match get_user_choice() {
             Ok(choice) => match choice {
                 1 => wireless_debugging_menu(&mut state),
-                2 => list_all_packages(&mut state),
-                3 => load_bloatware_list(&mut state),
+                2 => {
+                    if let Some(mode) = select_list_mode() {
+                        list_packages(&mut state, mode);
+                    }
+                }
+                3 => { load_bloatware_list(&mut state); }
                 4 => interactive_mode(&mut state),
                 5 => remove_single_package(&mut state),
                 6 => restore_package(&mut state),
                 7 => search_packages(&mut state),
-                8 => list_connected_devices(),
-                9 => create_backup(&state),
-                10 => show_device_info(&mut state),
-                11 => list_backups(),
+                8 => { list_connected_devices(&state); }
+                9 => { create_backup(&state); }
+                10 => { show_device_info(&mut state); }
+                11 => list_backups(&state),
                 12 => restore_from_backup(&mut state),
-                13 => {
+                13 => select_device(&mut state),
+                14 => {
+                    state.dry_run = !state.dry_run;
+                    if state.dry_run {
+                        println!("{}", "Dry-run enabled: commands will be printed, not run".magenta());
+                    } else {
+                        println!("{}", "Dry-run disabled".green());
+                    }
+                }
+                15 => select_max_tier(&mut state),
+                16 => select_users(&mut state),
+                17 => { take_snapshot(&mut state, None); }
+                18 => compare_with_snapshot(&mut state),
+                19 => {
+                    if let Some(path) = choose_snapshot(&state, "Enter snapshot number to revert to (or 0 to cancel): ") {
+                        revert_to_snapshot(&mut state, &path, true);
+                    }
+                }
+                20 => {
+                    let path = get_user_input("Enter profile file: ");
+                    if path.is_empty() {
+                        println!("{}", "Operation cancelled.".yellow());
+                    } else {
+                        apply_profile(&mut state, Path::new(&path), true, false);
+                    }
+                }
+                21 => {
+                    let path = get_user_input("Save profile to: ");
+                    if path.is_empty() {
+                        println!("{}", "Operation cancelled.".yellow());
+                    } else {
+                        export_profile(&mut state, Path::new(&path));
+                    }
+                }
+                22 => {
                     println!("{}", "Exiting... Goodbye!".yellow());
                     break;
                 }
@@ -134,12 +544,353 @@ fn main() {
     }
 }
 
+// runs the command once per attached device, in parallel, returns the exit code
+fn run_fleet(command: &CliCommand, options: &Options, args: &[String]) -> i32 {
+    if !matches!(command, CliCommand::Remove { .. } | CliCommand::Restore { .. } | CliCommand::Apply { .. }) {
+        eprintln!("{} --all-devices works with remove, restore and apply", "Error:".red().bold());
+        return 2;
+    }
+    if !check_adb() {
+        eprintln!("{}", "Error: ADB is not installed or not in PATH".red().bold());
+        eprintln!("Download from: https://developer.android.com/tools/releases/platform-tools");
+        return AdbError::AdbMissing(String::new()).exit_code();
+    }
+
+    let devices: Vec<DeviceEntry> = match adb_backend(options).devices() {
+        Ok(devices) => devices.into_iter().filter(|d| d.is_ready()).collect(),
+        Err(e) => {
+            println!("{} {}", "Error:".red(), e);
+            return e.exit_code();
+        }
+    };
+    if devices.is_empty() {
+        println!("{}", "No devices connected".yellow());
+        return AdbError::NoDevice.exit_code();
+    }
+
+    // once here instead of in every device's process, which would all move the same files
+    if options.backup_dir.is_none() && !options.dry_run {
+        migrate_legacy_backups(&paths::legacy_backups_dir(), &default_backup_dir());
+    }
+
+    println!("{} {}", "Running on".cyan(), format!("{} device(s)", devices.len()).bright_white());
+    let runs = fleet::run(&devices, args);
+    fleet::print_matrix(&runs);
+
+    if runs.iter().all(|r| r.succeeded()) { 0 } else { 1 }
+}
+
+// runs a single command from the command line, returns the exit code
+fn run_command(command: CliCommand, options: Options) -> i32 {
+    if !check_adb() {
+        eprintln!("{}", "Error: ADB is not installed or not in PATH".red().bold());
+        eprintln!("Download from: https://developer.android.com/tools/releases/platform-tools");
+        return AdbError::AdbMissing(String::new()).exit_code();
+    }
+
+    execute(AppState::from_options(&options, false), command)
+}
+
+// runs the command against `state`, returns the exit code
+fn execute(mut state: AppState, command: CliCommand) -> i32 {
+    let ok = match command {
+        CliCommand::List { bloat: false, mode } => list_packages(&mut state, mode),
+        CliCommand::List { bloat: true, .. } => load_bloatware_list(&mut state),
+        CliCommand::Search { term } => find_packages(&mut state, &term),
+        CliCommand::Remove { packages, force, no_backup } => {
+            if force {
+                state.max_tier = Removal::Unsafe;
+            }
+            remove_packages(&mut state, &packages, !no_backup)
+        }
+        CliCommand::Restore { packages, backup: None } => {
+            if !check_device_connected(&mut state) {
+                print_device_error(&state);
+                false
+            } else {
+                let mut all_ok = true;
+                for package in &packages {
+                    all_ok &= restore_package_by_name(&state, package);
+                }
+                all_ok
+            }
+        }
+        CliCommand::Restore { backup: Some(backup), .. } => {
+            let path = if backup == "latest" {
+                match latest_backup(&mut state) {
+                    Ok(path) => path,
+                    Err(e) => {
+                        println!("{}", e.yellow());
+                        return 1;
+                    }
+                }
+            } else {
+                PathBuf::from(backup)
+            };
+            restore_backup_file(&mut state, &path, false)
+        }
+        CliCommand::Backup { bloat } => {
+            let loaded = if bloat {
+                scan_bloatware(&mut state)
+            } else {
+                fetch_packages(&mut state, ListMode::System)
+            };
+            match loaded {
+                Ok(()) => create_backup(&state),
+                Err(e) => {
+                    println!("{} {}", "Error:".red(), e);
+                    false
+                }
+            }
+        }
+        CliCommand::Snapshot { output } => take_snapshot(&mut state, output.as_deref().map(Path::new)),
+        CliCommand::Diff { from, to } => {
+            let from = snapshot::resolve(state.snapshot_dir.as_deref(), &from);
+            let to = to.map(|to| snapshot::resolve(state.snapshot_dir.as_deref(), &to)).transpose();
+            match (from, to) {
+                (Ok(from), Ok(to)) => diff_snapshot(&mut state, &from, to.as_deref()),
+                (Err(e), _) | (_, Err(e)) => {
+                    println!("{} {}", "Error:".red(), e);
+                    false
+                }
+            }
+        }
+        CliCommand::Revert { snapshot, force } => {
+            if force {
+                state.max_tier = Removal::Unsafe;
+            }
+            match snapshot::resolve(state.snapshot_dir.as_deref(), &snapshot) {
+                Ok(path) => revert_to_snapshot(&mut state, &path, false),
+                Err(e) => {
+                    println!("{} {}", "Error:".red(), e);
+                    false
+                }
+            }
+        }
+        CliCommand::Apply { profile, force, no_backup } => apply_profile(&mut state, Path::new(&profile), !no_backup, force),
+        CliCommand::Export { output } => export_profile(&mut state, Path::new(&output)),
+        CliCommand::Devices => list_connected_devices(&state),
+        CliCommand::Users => list_users(&mut state),
+        CliCommand::Info => show_device_info(&mut state),
+        CliCommand::Interactive | CliCommand::Help | CliCommand::Version => true,
+    };
+
+    // a device that could not be used gets its own code, see AdbError::exit_code
+    let device_error = state.device_error.borrow().clone();
+    match device_error {
+        _ if ok => 0,
+        Some(e) => e.exit_code(),
+        None => 1,
+    }
+}
+
 impl AppState {
-    fn new() -> Self {
+    fn from_options(options: &Options, interactive: bool) -> Self {
+        let mut state = Self::with_backend(adb_backend(options), options.serial.clone(), interactive);
+        state.dry_run = options.dry_run;
+        state.max_tier = options.max_tier;
+        if !options.users.is_empty() {
+            state.users = options.users.clone();
+        }
+        state.all_users = options.all_users;
+        state.pull_apks = options.pull_apks;
+        state.read_labels = options.read_labels;
+        if let Some(dir) = &options.backup_dir {
+            state.backup_dir = dir.clone();
+        } else if !state.dry_run && !fleet::is_child() {
+            // with --all-devices the parent has moved them already, see run_fleet
+            migrate_legacy_backups(&paths::legacy_backups_dir(), &state.backup_dir);
+        }
+        if options.snapshot_dir.is_some() {
+            state.snapshot_dir = options.snapshot_dir.clone();
+        }
+        load_bloat_lists(&mut state, &options.lists);
+        state
+    }
+
+    fn with_backend(backend: Arc<dyn DeviceBackend>, serial: Option<String>, interactive: bool) -> Self {
         AppState {
             packages: Vec::new(),
             connection: ConnectionType::None,
             connected_device: String::new(),
+            serial,
+            interactive,
+            backend,
+            backup_dir: default_backup_dir(),
+            dry_run: false,
+            bloat_list: BloatList::builtin(),
+            max_tier: Removal::Recommended,
+            users: vec![0],
+            all_users: false,
+            snapshots: RefCell::new(BTreeMap::new()),
+            label_dir: paths::labels_dir(),
+            pull_apks: false,
+            read_labels: false,
+            snapshot_dir: paths::snapshots_dir(),
+            session: RefCell::new(None),
+            device_error: RefCell::new(None),
+        }
+    }
+
+    fn target(&self) -> Option<&str> {
+        if self.connected_device.is_empty() {
+            None
+        } else {
+            Some(&self.connected_device)
+        }
+    }
+
+    // the selected users, or every user on the device with --user all
+    fn users(&self) -> Vec<u32> {
+        if self.all_users {
+            if let Ok(users) = device_users(self) {
+                if !users.is_empty() {
+                    return users.iter().map(|u| u.id).collect();
+                }
+            }
+        }
+        self.users.clone()
+    }
+
+    // packages of the first selected user
+    fn installed_packages(&self) -> Result<Rc<PackageSnapshot>, AdbError> {
+        let user = self.users().first().copied().ok_or_else(|| AdbError::Other("no user selected".to_string()))?;
+        self.user_packages(user)
+    }
+
+    // a user's packages, listed once per device and cached until invalidated
+    fn user_packages(&self, user: u32) -> Result<Rc<PackageSnapshot>, AdbError> {
+        if let Some(snapshot) = self.snapshots.borrow().get(&user) {
+            if snapshot.serial == self.connected_device {
+                return Ok(snapshot.clone());
+            }
+        }
+
+        let names = |output: String| -> HashSet<String> {
+            output.lines()
+                .filter_map(|l| l.trim().strip_prefix("package:"))
+                .map(|name| name.to_string())
+                .collect()
+        };
+
+        let user_arg = user.to_string();
+        let list = |flags: &[&str]| {
+            let mut args = vec!["pm", "list", "packages"];
+            args.extend_from_slice(flags);
+            args.extend(["--user", &user_arg]);
+            self.shell(&args)
+        };
+
+        let listing = list(&["-f", "-i", "-U", "--show-versioncode", "-u"])?;
+        let system = names(list(&["-s", "-u"])?);
+        let installed = names(list(&[])?);
+        let disabled = names(list(&["-d", "-u"])?);
+
+        let packages: BTreeMap<String, Package> = listing.lines()
+            .filter_map(Package::parse_listing)
+            .map(|mut package| {
+                package.is_system = system.contains(&package.name);
+                package.installed = installed.contains(&package.name);
+                package.enabled = !disabled.contains(&package.name);
+                (package.name.clone(), package)
+            })
+            .collect();
+
+        let snapshot = Rc::new(PackageSnapshot {
+            serial: self.connected_device.clone(),
+            packages,
+        });
+        self.snapshots.borrow_mut().insert(user, snapshot.clone());
+        Ok(snapshot)
+    }
+
+    // call after anything that installs, removes or toggles packages
+    fn invalidate_packages(&self) {
+        self.snapshots.borrow_mut().clear();
+    }
+
+    // adb shell on the current device, through the open session if there is one
+    fn shell(&self, args: &[&str]) -> Result<String, AdbError> {
+        // a hung device would hang every command after it too
+        if let Some(e @ AdbError::Timeout(_)) = &*self.device_error.borrow() {
+            return Err(e.clone());
+        }
+        let mut session = self.session.borrow_mut();
+        if let Some(open) = session.as_mut() {
+            return match open.run(args) {
+                Ok((output, 0)) => Ok(output),
+                Ok((output, code)) => Err(self.record_error(AdbError::from_output(&output, Some(code)))),
+                Err(e) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => {
+                    *session = None;
+                    Err(self.record_error(AdbError::Timeout(String::new())))
+                }
+                Err(e) => {
+                    // the rest of the batch goes back to one adb shell per command
+                    *session = None;
+                    Err(AdbError::Other(format!("lost the device shell: {}", e)))
+                }
+            };
+        }
+        drop(session);
+        self.backend.shell(self.target(), args).map_err(|e| self.record_error(e))
+    }
+
+    // sends the following commands through one long-lived shell until end_session, instead
+    // of starting adb for each. a backend that cannot open one just runs them one by one
+    fn start_session(&self) {
+        if self.dry_run || self.session.borrow().is_some() {
+            return;
+        }
+        if let Ok(session) = self.backend.open_session(self.target()) {
+            *self.session.borrow_mut() = Some(session);
+        }
+    }
+
+    fn end_session(&self) {
+        self.session.borrow_mut().take();
+    }
+
+    // why there is no device to work on, after check_device_connected said so
+    fn device_error(&self) -> AdbError {
+        self.device_error.borrow().clone().unwrap_or(AdbError::NoDevice)
+    }
+
+    // keeps a connection error for the exit code, see run_command. passes `e` on
+    fn record_error(&self, e: AdbError) -> AdbError {
+        let e = e.on_device(&self.connected_device);
+        if e.is_connection() {
+            *self.device_error.borrow_mut() = Some(e.clone());
+        }
+        e
+    }
+
+    // the adb command line `shell(args)` amounts to
+    fn describe_shell(&self, args: &[&str]) -> String {
+        let mut parts = vec!["adb"];
+        if let Some(serial) = self.target() {
+            parts.extend(["-s", serial]);
+        }
+        parts.push("shell");
+        parts.extend_from_slice(args);
+        parts.join(" ")
+    }
+}
+
+fn print_dry_run(state: &AppState, args: &[&str]) {
+    println!("{} {}", "[dry-run]".magenta().bold(), state.describe_shell(args));
+}
+
+// merges the config dir lists and then the --list files into the built-in list
+fn load_bloat_lists(state: &mut AppState, extra: &[PathBuf]) {
+    let mut files = paths::lists_dir()
+        .map(|dir| bloatlist::list_files_in(&dir))
+        .unwrap_or_default();
+    files.extend_from_slice(extra);
+
+    for file in files {
+        match bloatlist::load_list_file(&file) {
+            Ok(entries) => state.bloat_list.merge(entries),
+            Err(e) => eprintln!("{} {}", "Could not load bloat list:".red(), e),
         }
     }
 }
@@ -175,9 +926,27 @@ fn display_main_menu(state: &AppState) {
         }
     }
 
+    if state.dry_run {
+        println!("{}", format!(" Mode:   {}", "DRY RUN (no changes are made)".magenta().bold()).cyan());
+    }
+    println!("{}", format!(" Tier:   up to {}", tier_colored(state.max_tier)).cyan());
+
+    if state.all_users {
+        println!("{}", format!(" Users:  {}", "all".bright_white()).cyan());
+    } else if state.users != [0] {
+        let users: Vec<String> = state.users.iter().map(|u| u.to_string()).collect();
+        println!("{}", format!(" Users:  {}", users.join(", ").bright_white()).cyan());
+    }
+
+    if !state.connected_device.is_empty() {
+        println!("{}", format!(" Device: {}", state.connected_device.bright_white()).cyan());
+    } else if let Some(serial) = &state.serial {
+        println!("{}", format!(" Device: {} (not attached)", serial.bright_white()).cyan());
+    }
+
     println!("{}", "===========================================".cyan());
     println!("{}", "  1. Wireless debugging menu              ".cyan());
-    println!("{}", "  2. List all system packages             ".cyan());
+    println!("{}", "  2. List packages                        ".cyan());
     println!("{}", "  3. List common bloatware                ".cyan());
     println!("{}", "  4. Interactive removal mode             ".cyan());
     println!("{}", "  5. Remove specific package              ".cyan());
@@ -188,7 +957,16 @@ fn display_main_menu(state: &AppState) {
     println!("{}", " 10. Show device info                     ".cyan());
     println!("{}", " 11. List all backups                     ".cyan());
     println!("{}", " 12. Restore from backup                  ".cyan());
-    println!("{}", " 13. Exit                                 ".cyan());
+    println!("{}", " 13. Select device                        ".cyan());
+    println!("{}", " 14. Toggle dry-run mode                  ".cyan());
+    println!("{}", " 15. Set maximum safety tier              ".cyan());
+    println!("{}", " 16. Select users / profiles              ".cyan());
+    println!("{}", " 17. Take package snapshot                ".cyan());
+    println!("{}", " 18. Compare device with snapshot         ".cyan());
+    println!("{}", " 19. Revert device to snapshot            ".cyan());
+    println!("{}", " 20. Apply debloat profile                ".cyan());
+    println!("{}", " 21. Export device as profile             ".cyan());
+    println!("{}", " 22. Exit                                 ".cyan());
     println!("{}", "===========================================".cyan());
 }
 
@@ -230,6 +1008,15 @@ fn confirm_action(prompt: &str) -> bool {
     response.to_lowercase() == "yes" || response.to_lowercase() == "y"
 }
 
+// the adb server's own protocol unless --adb-binary asks for the binary
+fn adb_backend(options: &Options) -> Arc<dyn DeviceBackend> {
+    if options.adb_binary {
+        Arc::new(AdbBackend)
+    } else {
+        Arc::new(AdbClient::from_env())
+    }
+}
+
 fn check_adb() -> bool {
     Command::new("adb")
         .arg("version")
@@ -263,7 +1050,7 @@ fn install_adb_linux() {
         if confirm_action("Install ADB using nix-env?") {
             println!("{}", "Installing android-tools...".yellow());
             let status = Command::new("nix-env")
-                .args(&["-iA", "nixpkgs.android-tools"])
+                .args(["-iA", "nixpkgs.android-tools"])
                 .status();
             
             match status {
@@ -280,7 +1067,7 @@ fn install_adb_linux() {
             println!("{}", "Installing android-tools...".yellow());
             println!("{}", "This requires sudo access".yellow());
             let status = Command::new("sudo")
-                .args(&["pacman", "-S", "--noconfirm", "android-tools"])
+                .args(["pacman", "-S", "--noconfirm", "android-tools"])
                 .status();
             
             match status {
@@ -297,12 +1084,12 @@ fn install_adb_linux() {
             println!("{}", "Installing adb...".yellow());
             println!("{}", "This requires sudo access".yellow());
             let update = Command::new("sudo")
-                .args(&["apt", "update"])
+                .args(["apt", "update"])
                 .status();
                 
             if update.is_ok() {
                 let status = Command::new("sudo")
-                    .args(&["apt", "install", "-y", "adb"])
+                    .args(["apt", "install", "-y", "adb"])
                     .status();
                 
                 match status {
@@ -320,7 +1107,7 @@ fn install_adb_linux() {
             println!("{}", "Installing android-tools...".yellow());
             println!("{}", "This requires sudo access".yellow());
             let status = Command::new("sudo")
-                .args(&["dnf", "install", "-y", "android-tools"])
+                .args(["dnf", "install", "-y", "android-tools"])
                 .status();
             
             match status {
@@ -337,7 +1124,7 @@ fn install_adb_linux() {
             println!("{}", "Installing android-tools...".yellow());
             println!("{}", "This requires sudo access".yellow());
             let status = Command::new("sudo")
-                .args(&["zypper", "install", "-y", "android-tools"])
+                .args(["zypper", "install", "-y", "android-tools"])
                 .status();
             
             match status {
@@ -360,7 +1147,7 @@ fn install_adb_windows() {
         if confirm_action("Install ADB using winget?") {
             println!("{}", "Installing Android SDK Platform Tools...".yellow());
             let status = Command::new("winget")
-                .args(&["install", "Google.PlatformTools"])
+                .args(["install", "Google.PlatformTools"])
                 .status();
             
             match status {
@@ -379,7 +1166,7 @@ fn install_adb_windows() {
         if confirm_action("Install ADB using Chocolatey?") {
             println!("{}", "Installing adb...".yellow());
             let status = Command::new("choco")
-                .args(&["install", "adb", "-y"])
+                .args(["install", "adb", "-y"])
                 .status();
             
             match status {
@@ -407,7 +1194,7 @@ fn install_adb_macos() {
         if confirm_action("Install ADB using Homebrew?") {
             println!("{}", "Installing android-platform-tools...".yellow());
             let status = Command::new("brew")
-                .args(&["install", "android-platform-tools"])
+                .args(["install", "android-platform-tools"])
                 .status();
             
             match status {
@@ -424,77 +1211,217 @@ fn install_adb_macos() {
     println!("https://developer.android.com/tools/releases/platform-tools");
 }
 
-fn check_device_connected(state: &mut AppState) -> bool {
-    let output = match Command::new("adb").arg("devices").output() {
-        Ok(out) => out,
-        Err(_) => return false,
-    };
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
+// serials of attached devices that are ready to use
+fn get_attached_devices(state: &AppState) -> Result<Vec<String>, AdbError> {
+    let devices = state.backend.devices()?;
+    Ok(devices.into_iter()
+        .filter(|d| d.is_ready())
+        .map(|d| d.serial)
+        .collect())
+}
 
-    for (i, line) in stdout.lines().enumerate() {
-        if i > 0 && line.contains("\tdevice") && !line.contains("List of") {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if !parts.is_empty() {
-                state.connected_device = parts[0].to_string();
-                state.connection = if parts[0].contains(':') {
-                    ConnectionType::Wireless
-                } else {
-                    ConnectionType::Usb
-                };
-                return true;
-            }
+// finds the device to work on. when there is none, state.device_error says why
+fn check_device_connected(state: &mut AppState) -> bool {
+    match choose_device(state) {
+        Ok(serial) => {
+            state.connection = if serial.contains(':') {
+                ConnectionType::Wireless
+            } else {
+                ConnectionType::Usb
+            };
+            state.connected_device = serial;
+            *state.device_error.get_mut() = None;
+            true
+        }
+        Err(e) => {
+            state.connection = ConnectionType::None;
+            state.connected_device.clear();
+            *state.device_error.get_mut() = Some(e);
+            false
         }
     }
-
-    state.connection = ConnectionType::None;
-    state.connected_device.clear();
-    false
 }
 
-fn execute_adb_command(args: &[&str]) -> Result<String, String> {
-    let output = Command::new("adb")
-        .args(args)
-        .output()
-        .map_err(|e| format!("Failed to execute ADB: {}", e))?;
-
-    if output.status.success() {
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
-    } else {
-        Err(String::from_utf8_lossy(&output.stderr).to_string())
+fn choose_device(state: &mut AppState) -> Result<String, AdbError> {
+    let devices = state.backend.devices()?;
+    let ready: Vec<String> = devices.iter()
+        .filter(|d| d.is_ready())
+        .map(|d| d.serial.clone())
+        .collect();
+
+    match &state.serial {
+        Some(serial) => match devices.iter().find(|d| &d.serial == serial) {
+            Some(device) if device.is_ready() => Ok(serial.clone()),
+            Some(device) => Err(not_ready(device)),
+            None => Err(AdbError::DeviceNotFound(serial.clone())),
+        },
+        None => match ready.len() {
+            // an attached phone that cannot be used yet says more than "no device"
+            0 => Err(devices.first().map(not_ready).unwrap_or(AdbError::NoDevice)),
+            1 => Ok(ready[0].clone()),
+            _ if state.interactive => {
+                let picked = pick_device(&ready);
+                state.serial = picked.clone();
+                picked.ok_or(AdbError::NoDevice)
+            }
+            _ => Err(AdbError::MultipleDevices(ready)),
+        },
     }
 }
 
-// get temp dir for backups
-fn get_temp_backup_dir() -> PathBuf {
-    env::temp_dir().join("android_debloater_backups")
+fn not_ready(device: &DeviceEntry) -> AdbError {
+    let serial = device.serial.clone();
+    match device.state.as_str() {
+        "unauthorized" | "authorizing" => AdbError::Unauthorized(serial),
+        "offline" => AdbError::Offline(serial),
+        // "no permissions (user in plugdev group; are your udev rules wrong?)"
+        "no" => AdbError::Other(format!("no permission to use device {}, check the udev rules", serial)),
+        other => AdbError::Other(format!("device {} is in {} mode", serial, other)),
+    }
 }
 
-fn get_temp_backup_path() -> PathBuf {
-    let backup_dir = get_temp_backup_dir();
-    fs::create_dir_all(&backup_dir).ok();
-    
-    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
-    backup_dir.join(format!("backup_{}.json", timestamp))
+// why check_device_connected failed, and what to do about it
+fn print_device_error(state: &AppState) {
+    let error = state.device_error();
+    println!("{} {}", "Error:".red(), error.to_string().red());
+    if let Some(hint) = error.hint() {
+        println!("{}", hint.yellow());
+    }
 }
 
-fn show_temp_location() {
-    let temp_dir = get_temp_backup_dir();
-    println!("{} {}", "Backup location:".cyan(), temp_dir.display().to_string().bright_white());
+fn pick_device(devices: &[String]) -> Option<String> {
+    println!();
+    println!("{}", "Multiple devices attached:".yellow().bold());
+    for (i, serial) in devices.iter().enumerate() {
+        let kind = if serial.contains(':') { "[Wireless]".green() } else { "[USB]     ".blue() };
+        println!("{} {} {}", format!("[{}]", i + 1).bright_black(), kind, serial.bright_white());
+    }
+
+    let choice = get_user_input("Select device number (or 0 to cancel): ");
+    match choice.parse::<usize>() {
+        Ok(n) if n > 0 && n <= devices.len() => Some(devices[n - 1].clone()),
+        _ => {
+            println!("{}", "No device selected".yellow());
+            None
+        }
+    }
+}
+
+fn select_device(state: &mut AppState) {
+    let devices = match get_attached_devices(state) {
+        Ok(devices) => devices,
+        Err(e) => {
+            println!("{} {}", "Error:".red(), e);
+            return;
+        }
+    };
+
+    if devices.is_empty() {
+        println!("{}", "No devices connected".yellow());
+        return;
+    }
+
+    if let Some(serial) = pick_device(&devices) {
+        println!("{} {}", "Using device:".green(), serial.bright_white());
+        state.serial = Some(serial);
+        check_device_connected(state);
+    }
+}
+
+// the device serial is part of the name, so phones backed up at the same time don't clash
+fn get_backup_path(state: &AppState) -> PathBuf {
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+    let serial: String = state.connected_device.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+    if serial.is_empty() {
+        state.backup_dir.join(format!("backup_{}.json", timestamp))
+    } else {
+        state.backup_dir.join(format!("backup_{}_{}.json", timestamp, serial))
+    }
+}
+
+// the newest backup taken from the connected device. another phone's backup is never
+// picked for it, only when the device cannot be identified is it the newest of all
+fn latest_backup(state: &mut AppState) -> Result<PathBuf, String> {
+    let backups = find_backups(&state.backup_dir).unwrap_or_default();
+    if backups.is_empty() {
+        return Err("No backups found".to_string());
+    }
+    let serial = if check_device_connected(state) { device_identity(state).serial } else { String::new() };
+    if serial.is_empty() {
+        return Ok(backups[0].clone());
+    }
+    backups.into_iter()
+        .find(|path| read_backup(path).is_ok_and(|b| b.device.is_some_and(|d| d.serial == serial)))
+        .ok_or_else(|| format!("No backup for this device ({})", serial))
+}
+
+fn default_backup_dir() -> PathBuf {
+    paths::backups_dir().unwrap_or_else(paths::legacy_backups_dir)
+}
+
+// backups used to live in the temp dir, which gets wiped on reboot. moves any
+// found there to `to` once, returns how many were moved
+fn migrate_legacy_backups(from: &Path, to: &Path) -> usize {
+    if from == to {
+        return 0;
+    }
+    let backups = match find_backups(from) {
+        Ok(backups) if !backups.is_empty() => backups,
+        _ => return 0,
+    };
+    if let Err(e) = fs::create_dir_all(to) {
+        println!("{} {}", "Could not create backup directory:".red(), e);
+        return 0;
+    }
+
+    let mut moved = 0;
+    for backup in &backups {
+        let Some(name) = backup.file_name() else { continue };
+        let target = to.join(name);
+        if target.exists() {
+            continue;
+        }
+        // the temp dir is often another filesystem, so rename can fail
+        let ok = fs::rename(backup, &target).is_ok()
+            || (fs::copy(backup, &target).is_ok() && fs::remove_file(backup).is_ok());
+        if ok {
+            moved += 1;
+        }
+    }
+
+    if moved > 0 {
+        println!("{} {} {} {}", format!("Moved {} backup(s) from", moved).cyan(), from.display(), "to".cyan(), to.display());
+    }
+    // only goes away once it is empty
+    fs::remove_dir(from).ok();
+    moved
+}
+
+fn show_backup_location(state: &AppState) {
+    println!("{} {}", "Backup location:".cyan(), state.backup_dir.display().to_string().bright_white());
 }
 
 // auto backup before removal
-fn create_backup_auto(packages: &[String]) -> Result<String, String> {
-    let backup_path = get_temp_backup_path();
+fn create_backup_auto(state: &AppState, packages: &[String]) -> Result<String, String> {
+    let backup_path = get_backup_path(state);
+    if !state.dry_run {
+        fs::create_dir_all(&state.backup_dir).ok();
+    }
     
-    let backup = Backup {
-        timestamp: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-        packages: packages.to_vec(),
-    };
+    let mut backup = Backup::capture(state, packages.to_vec());
+    if state.pull_apks {
+        backup.apks = pull_apks(state, &backup_path, packages);
+    }
     
     match serde_json::to_string_pretty(&backup) {
         Ok(json) => {
-            if let Err(e) = fs::write(&backup_path, json) {
+            if state.dry_run {
+                println!("{} {}", "[dry-run] would write backup:".magenta().bold(), backup_path.display());
+                println!("{}", json);
+                Ok(backup_path.to_string_lossy().to_string())
+            } else if let Err(e) = fs::write(&backup_path, json) {
                 Err(format!("Failed to create backup: {}", e))
             } else {
                 Ok(backup_path.to_string_lossy().to_string())
@@ -504,68 +1431,212 @@ fn create_backup_auto(packages: &[String]) -> Result<String, String> {
     }
 }
 
-fn list_backups() {
+// the folder a package's apks are pulled into, next to the backup file
+fn apk_dir(backup_path: &Path, package: &str) -> PathBuf {
+    backup_path.with_extension("").join(package)
+}
+
+// copies base and split apks of every package one of the selected users has installed
+fn pull_apks(state: &AppState, backup_path: &Path, packages: &[String]) -> BTreeMap<String, Vec<ApkFile>> {
+    let mut pulled = BTreeMap::new();
+    let users = state.users();
+
+    for package in packages {
+        let Some(user) = users.iter().find(|u| state.user_packages(**u).is_ok_and(|s| s.contains(package))) else {
+            continue;
+        };
+        let dir = apk_dir(backup_path, package);
+
+        if state.dry_run {
+            println!("{} {} {}", "[dry-run] would pull the apks of".magenta().bold(), package, format!("into {}", dir.display()).bright_black());
+            continue;
+        }
+
+        print!("{} {}...", "Pulling apks of".yellow(), package.bright_white());
+        io::stdout().flush().ok();
+
+        match pull_package_apks(state, package, *user, &dir) {
+            Ok(files) => {
+                let size: u64 = files.iter().map(|f| f.size).sum();
+                println!(" {} ({} file(s), {} KB)", "OK".green(), files.len(), size / 1024);
+                pulled.insert(package.clone(), files);
+            }
+            Err(e) => {
+                println!(" {} {}", "FAILED".red(), e.trim().bright_black());
+                fs::remove_dir_all(&dir).ok();
+            }
+        }
+    }
+    pulled
+}
+
+fn pull_package_apks(state: &AppState, package: &str, user: u32, dir: &Path) -> Result<Vec<ApkFile>, String> {
+    let user_arg = user.to_string();
+    let listing = state.shell(&["pm", "path", "--user", &user_arg, package]).map_err(|e| e.to_string())?;
+    let remotes = apk_backup::parse_pm_path(&listing);
+    if remotes.is_empty() {
+        return Err("pm path found no apks".to_string());
+    }
+
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let mut files = Vec::new();
+    for remote in &remotes {
+        let name = apk_backup::file_name(remote);
+        state.backend.pull(state.target(), remote, &dir.join(name)).map_err(|e| state.record_error(e).to_string())?;
+        files.push(apk_backup::describe(dir, name)?);
+    }
+    Ok(files)
+}
+
+// reinstalls a package from the apks saved with a backup, after checking their checksums
+fn install_backup_apks(state: &AppState, dir: &Path, files: &[ApkFile], user: u32) -> Result<RestoreAction, String> {
+    apk_backup::verify(dir, files)?;
+    let paths: Vec<PathBuf> = files.iter().map(|f| dir.join(&f.file)).collect();
+
+    if state.dry_run {
+        let names: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+        println!("{} adb install-multiple -r --user {} {}", "[dry-run]".magenta().bold(), user, names.join(" "));
+    } else {
+        let paths: Vec<&Path> = paths.iter().map(PathBuf::as_path).collect();
+        state.invalidate_packages();
+        state.backend.install_multiple(state.target(), &paths, user).map_err(|e| state.record_error(e).to_string())?;
+    }
+    Ok(RestoreAction::InstallApks)
+}
+
+// stores the removal results in the backup written before the removal
+fn record_removal_outcomes(backup_path: &Path, results: &[RemovalResult]) -> Result<(), String> {
+    let mut backup = read_backup(backup_path)?;
+
+    for result in results {
+        let entry = backup.entries.iter_mut().find(|e| e.user == result.user && e.package == result.package);
+        match entry {
+            Some(entry) => entry.action = Some(result.outcome.clone()),
+            None => backup.entries.push(BackupEntry {
+                package: result.package.clone(),
+                user: result.user,
+                prior: None,
+                action: Some(result.outcome.clone()),
+            }),
+        }
+    }
+
+    let json = serde_json::to_string_pretty(&backup)
+        .map_err(|e| format!("Failed to serialize backup: {}", e))?;
+    fs::write(backup_path, json).map_err(|e| format!("Failed to update backup: {}", e))
+}
+
+fn print_removal_summary(results: &[RemovalResult]) {
+    let count = |f: fn(&RemovalOutcome) -> bool| results.iter().filter(|r| f(&r.outcome)).count();
+
+    println!();
+    println!("{}", "Removal summary:".cyan().bold());
+    println!("  {} {}", "Uninstalled:".green(), count(|o| *o == RemovalOutcome::Uninstalled));
+    println!("  {} {}", "Disabled:   ".yellow(), count(|o| *o == RemovalOutcome::Disabled));
+    println!("  {} {}", "Failed:     ".red(), count(|o| matches!(o, RemovalOutcome::Failed(_))));
+
+    for result in results {
+        if let RemovalOutcome::Failed(reason) = &result.outcome {
+            let user = if result.user == 0 { String::new() } else { format!(" (user {})", result.user) };
+            println!("    - {}{} {}", result.package.red(), user, reason.trim().bright_black());
+        }
+    }
+}
+
+// summary plus backup bookkeeping once a batch of removals is done
+fn finish_removal(state: &AppState, backup_path: Option<&str>, results: &[RemovalResult]) {
+    if state.dry_run {
+        println!("{}", "\nDry run: nothing was removed".magenta().bold());
+        return;
+    }
+
+    print_removal_summary(results);
+
+    if let Some(path) = backup_path {
+        if let Err(e) = record_removal_outcomes(Path::new(path), results) {
+            println!("{} {}", "Could not record results in backup:".red(), e);
+        }
+    }
+    show_backup_location(state);
+}
+
+// backup files in the backup dir, newest first
+fn find_backups(backup_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    if !backup_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups: Vec<PathBuf> = fs::read_dir(backup_dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.extension()
+                .and_then(|s| s.to_str())
+                .map(|s| s == "json")
+                .unwrap_or(false)
+        })
+        .collect();
+
+    backups.sort();
+    backups.reverse();
+    Ok(backups)
+}
+
+fn print_backup_entries(backups: &[PathBuf]) {
+    for (i, path) in backups.iter().enumerate() {
+        let filename = path.file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown");
+        
+        // try read the backup to show package count
+        if let Ok(backup) = read_backup(path) {
+            let users = if backup.users == [0] {
+                String::new()
+            } else {
+                let ids: Vec<String> = backup.users.iter().map(|u| u.to_string()).collect();
+                format!(", users {}", ids.join(", "))
+            };
+            let model = match &backup.device {
+                Some(device) if !device.model.is_empty() => format!(", {}", device.model),
+                _ => String::new(),
+            };
+            println!("{} {} - {} ({} packages{}{})", 
+                format!("[{}]", i + 1).bright_black(),
+                filename.bright_white(),
+                backup.timestamp.cyan(),
+                backup.packages.len(),
+                users,
+                model
+            );
+            continue;
+        }
+        
+        println!("{} {}", 
+            format!("[{}]", i + 1).bright_black(),
+            filename.bright_white()
+        );
+    }
+}
+
+fn list_backups(state: &AppState) {
     println!();
     println!("{}", "=========================================".cyan());
     println!("{}", "  Available Backups".cyan().bold());
     println!("{}", "=========================================".cyan());
     
-    let backup_dir = get_temp_backup_dir();
-    
-    if !backup_dir.exists() {
-        println!("{}", "No backups found".yellow());
-        println!("{} {}", "Backup directory:".cyan(), backup_dir.display());
-        return;
-    }
-    
-    match fs::read_dir(&backup_dir) {
-        Ok(entries) => {
-            let mut backups: Vec<_> = entries
-                .filter_map(|e| e.ok())
-                .filter(|e| {
-                    e.path().extension()
-                        .and_then(|s| s.to_str())
-                        .map(|s| s == "json")
-                        .unwrap_or(false)
-                })
-                .collect();
-            
+    show_backup_location(state);
+
+    match find_backups(&state.backup_dir) {
+        Ok(backups) => {
             if backups.is_empty() {
                 println!("{}", "No backups found".yellow());
                 return;
             }
             
-            backups.sort_by_key(|e| e.path());
-            backups.reverse();
-            
-            for (i, entry) in backups.iter().enumerate() {
-                let path = entry.path();
-                let filename = path.file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("unknown");
-                
-                // try read the backup to show package count
-                if let Ok(content) = fs::read_to_string(&path) {
-                    if let Ok(backup) = serde_json::from_str::<Backup>(&content) {
-                        println!("{} {} - {} ({} packages)", 
-                            format!("[{}]", i + 1).bright_black(),
-                            filename.bright_white(),
-                            backup.timestamp.cyan(),
-                            backup.packages.len()
-                        );
-                        continue;
-                    }
-                }
-                
-                println!("{} {}", 
-                    format!("[{}]", i + 1).bright_black(),
-                    filename.bright_white()
-                );
-            }
+            print_backup_entries(&backups);
             
             println!("{}", "=========================================".cyan());
             println!("{} {}", "Total backups:".cyan(), backups.len().to_string().bright_white());
-            show_temp_location();
         }
         Err(e) => {
             println!("{} {}", "Error reading backup directory:".red(), e);
@@ -575,141 +1646,160 @@ fn list_backups() {
 
 fn restore_from_backup(state: &mut AppState) {
     if !check_device_connected(state) {
-        println!("{}", "Error: No device connected!".red());
+        print_device_error(state);
         return;
     }
     
-    let backup_dir = get_temp_backup_dir();
+    let backups = match find_backups(&state.backup_dir) {
+        Ok(backups) => backups,
+        Err(e) => {
+            println!("{} {}", "Error reading backup directory:".red(), e);
+            return;
+        }
+    };
     
-    if !backup_dir.exists() {
+    if backups.is_empty() {
         println!("{}", "No backups found".yellow());
         return;
     }
     
-    match fs::read_dir(&backup_dir) {
-        Ok(entries) => {
-            let mut backups: Vec<_> = entries
-                .filter_map(|e| e.ok())
-                .filter(|e| {
-                    e.path().extension()
-                        .and_then(|s| s.to_str())
-                        .map(|s| s == "json")
-                        .unwrap_or(false)
-                })
-                .collect();
-            
-            if backups.is_empty() {
-                println!("{}", "No backups found".yellow());
-                return;
+    println!();
+    println!("{}", "Available backups:".cyan().bold());
+    println!();
+    
+    print_backup_entries(&backups);
+    
+    println!();
+    let choice = get_user_input("Enter backup number to restore (or 0 to cancel): ");
+    
+    let index: usize = match choice.parse() {
+        Ok(n) if n > 0 && n <= backups.len() => n - 1,
+        Ok(0) => {
+            println!("{}", "Cancelled".yellow());
+            return;
+        }
+        _ => {
+            println!("{}", "Invalid choice".red());
+            return;
+        }
+    };
+    
+    restore_backup_file(state, &backups[index], true);
+}
+
+// restores every package listed in a backup file, asking first when `confirm` is set
+fn restore_backup_file(state: &mut AppState, backup_path: &Path, confirm: bool) -> bool {
+    if !check_device_connected(state) {
+        print_device_error(state);
+        return false;
+    }
+    
+    let backup = match read_backup(backup_path) {
+        Ok(backup) => backup,
+        Err(e) => {
+            println!("{} {}", "Error:".red(), e);
+            return false;
+        }
+    };
+    
+    println!();
+    println!("{} {}", "Backup timestamp:".cyan(), backup.timestamp);
+    if let Some(device) = &backup.device {
+        if !device.model.is_empty() {
+            println!("{} {}", "Backup device:".cyan(), device.model);
+        }
+    }
+    println!("{} {}", "Packages to restore:".cyan(), backup.packages.len());
+    println!();
+
+    if let Some(device) = &backup.device {
+        let mismatches = identity_mismatches(device, &device_identity(state));
+        if !mismatches.is_empty() {
+            println!("{}", "Warning: this backup may not match the connected device".yellow().bold());
+            for mismatch in &mismatches {
+                println!("  - {}", mismatch.yellow());
             }
-            
-            backups.sort_by_key(|e| e.path());
-            backups.reverse();
-            
-            println!();
-            println!("{}", "Available backups:".cyan().bold());
             println!();
-            
-            for (i, entry) in backups.iter().enumerate() {
-                let path = entry.path();
-                let filename = path.file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("unknown");
-                
-                if let Ok(content) = fs::read_to_string(&path) {
-                    if let Ok(backup) = serde_json::from_str::<Backup>(&content) {
-                        println!("{} {} - {} ({} packages)", 
-                            format!("[{}]", i + 1).bright_black(),
-                            filename.bright_white(),
-                            backup.timestamp.cyan(),
-                            backup.packages.len()
-                        );
-                        continue;
-                    }
-                }
-                
-                println!("{} {}", 
-                    format!("[{}]", i + 1).bright_black(),
-                    filename.bright_white()
-                );
+        }
+    }
+    
+    if confirm && !confirm_action("Restore these packages?") {
+        println!("{}", "Cancelled".yellow());
+        return false;
+    }
+    
+    println!();
+    
+    let mut restored = 0;
+    let mut unchanged = 0;
+    let mut failed = 0;
+    
+    state.start_session();
+    for entry in &backup.entries {
+        let (user, package) = (entry.user, &entry.package);
+        let hint = entry.action.as_ref();
+        let user_note = if backup.users == [0] { String::new() } else { format!(" (user {})", user) };
+
+        // removal never went through, nothing to undo
+        if let Some(RemovalOutcome::Failed(_)) = hint {
+            println!("{} {}{} {}", "Skipping".bright_black(), package.bright_white(), user_note, "(was not removed)".bright_black());
+            continue;
+        }
+        // the user did not have it before the removal either
+        if entry.prior.is_some_and(|p| !p.installed) {
+            println!("{} {}{} {}", "Skipping".bright_black(), package.bright_white(), user_note, "(was not installed)".bright_black());
+            continue;
+        }
+
+        if state.dry_run {
+            println!("{} {}{}", "Restoring".yellow(), package.bright_white(), user_note);
+        } else {
+            print!("{} {}{}...", "Restoring".yellow(), package.bright_white(), user_note);
+            io::stdout().flush().ok();
+        }
+        
+        let result = restore_package_state(state, package, user, entry.prior.unwrap_or(PackageState::ACTIVE), hint)
+            .or_else(|e| match backup.apks.get(package) {
+                // install-existing only works while the device still has the apk
+                Some(files) => install_backup_apks(state, &apk_dir(backup_path, package), files, user)
+                    .map_err(|apk_error| format!("{} / {}", e, apk_error.trim())),
+                None => Err(e.to_string()),
+            });
+
+        match result {
+            Ok(_) if state.dry_run => {}
+            Ok(RestoreAction::Nothing) => {
+                println!(" {} ({})", "OK".green(), RestoreAction::Nothing.describe());
+                unchanged += 1;
             }
-            
-            println!();
-            let choice = get_user_input("Enter backup number to restore (or 0 to cancel): ");
-            
-            let index: usize = match choice.parse() {
-                Ok(n) if n > 0 && n <= backups.len() => n - 1,
-                Ok(0) => {
-                    println!("{}", "Cancelled".yellow());
-                    return;
-                }
-                _ => {
-                    println!("{}", "Invalid choice".red());
-                    return;
-                }
-            };
-            
-            let backup_path = backups[index].path();
-            
-            match fs::read_to_string(&backup_path) {
-                Ok(content) => {
-                    match serde_json::from_str::<Backup>(&content) {
-                        Ok(backup) => {
-                            println!();
-                            println!("{} {}", "Backup timestamp:".cyan(), backup.timestamp);
-                            println!("{} {}", "Packages to restore:".cyan(), backup.packages.len());
-                            println!();
-                            
-                            if !confirm_action("Restore these packages?") {
-                                println!("{}", "Cancelled".yellow());
-                                return;
-                            }
-                            
-                            println!();
-                            let mut restored = 0;
-                            let mut failed = 0;
-                            
-                            for package in &backup.packages {
-                                print!("{} {}...", "Restoring".yellow(), package.bright_white());
-                                io::stdout().flush().ok();
-                                
-                                let result = Command::new("adb")
-                                    .args(&["shell", "cmd", "package", "install-existing", package])
-                                    .output();
-                                
-                                match result {
-                                    Ok(output) if output.status.success() => {
-                                        println!(" {}", "OK".green());
-                                        restored += 1;
-                                    }
-                                    _ => {
-                                        println!(" {}", "FAILED".red());
-                                        failed += 1;
-                                    }
-                                }
-                            }
-                            
-                            println!();
-                            println!("{}", "Restore completed!".green().bold());
-                            println!("{} {}", "Restored:".green(), restored);
-                            if failed > 0 {
-                                println!("{} {}", "Failed:".red(), failed);
-                            }
-                        }
-                        Err(e) => {
-                            println!("{} {}", "Error parsing backup file:".red(), e);
-                        }
-                    }
-                }
-                Err(e) => {
-                    println!("{} {}", "Error reading backup file:".red(), e);
-                }
+            Ok(action) => {
+                println!(" {} ({})", "OK".green(), action.describe());
+                restored += 1;
+            }
+            Err(e) => {
+                println!(" {} {}", "FAILED".red(), e.trim().bright_black());
+                failed += 1;
             }
         }
-        Err(e) => {
-            println!("{} {}", "Error reading backup directory:".red(), e);
-        }
     }
+    state.end_session();
+    
+    if state.dry_run {
+        println!("{}", "Dry run: nothing was restored".magenta());
+        return failed == 0;
+    }
+    
+    println!();
+    println!("{}", "Restore completed!".green().bold());
+    println!("{} {}", "Restored:".green(), restored);
+    if unchanged > 0 {
+        println!("{} {}", "Already present:".cyan(), unchanged);
+    }
+    if failed > 0 {
+        println!("{} {}", "Failed:".red(), failed);
+    }
+    
+    failed == 0
 }
 
 
@@ -724,7 +1814,7 @@ fn wireless_debugging_menu(state: &mut AppState) {
                 3 => get_device_ip_automatically(state),
                 4 => enable_wireless_adb_on_device(state),
                 5 => disconnect_wireless(state),
-                6 => list_connected_devices(),
+                6 => { list_connected_devices(state); }
                 7 => return,
                 _ => println!("{}", "Invalid choice!".red()),
             },
@@ -756,36 +1846,27 @@ fn connect_wireless_pairing(state: &mut AppState) {
 
     println!("{}", "\nPairing with device...".yellow());
 
-    let result = Command::new("adb")
-        .arg("pair")
-        .arg(format!("{}:{}", ip, port))
-        .arg(&pairing_code)
-        .status();
-
-    match result {
-        Ok(status) if status.success() => {
+    match state.backend.pair(&format!("{}:{}", ip, port), &pairing_code) {
+        Ok(_) => {
             println!("{}", "Pairing successful!".green().bold());
 
             let conn_port = get_user_input("\nEnter connection port (usually 5555 or shown on device): ");
 
             println!("{}", "Connecting...".yellow());
-            let connect_result = Command::new("adb")
-                .arg("connect")
-                .arg(format!("{}:{}", ip, conn_port))
-                .status();
-
-            if let Ok(status) = connect_result {
-                if status.success() {
+            match state.backend.connect(&format!("{}:{}", ip, conn_port)) {
+                Ok(_) => {
                     thread::sleep(Duration::from_millis(500));
                     println!("{}", "Connected wirelessly!".green().bold());
                     state.connection = ConnectionType::Wireless;
                     state.connected_device = format!("{}:{}", ip, conn_port);
-                } else {
-                    println!("{}", "Connection failed".red());
+                    state.serial = Some(state.connected_device.clone());
+                }
+                Err(e) => {
+                    println!("{} {}", "Connection failed:".red(), e);
                 }
             }
         }
-        _ => {
+        Err(_) => {
             println!("{}", "Pairing failed".red().bold());
             println!("\n{}", "Troubleshooting:".yellow());
             println!("  - IP address and port are correct");
@@ -818,15 +1899,13 @@ fn connect_wireless_legacy(state: &mut AppState) {
         println!("{}", "USB device detected!".green());
 
         println!("{}", "Enabling wireless debugging on device...".yellow());
-        let _ = Command::new("adb")
-            .args(&["tcpip", "5555"])
-            .status();
+        let _ = state.backend.tcpip(state.target(), 5555);
 
         thread::sleep(Duration::from_secs(2));
 
         println!("{}", "Detecting device IP address...".yellow());
 
-        let ip = match execute_adb_command(&["shell", "ip", "addr", "show", "wlan0"]) {
+        let ip = match state.shell(&["ip", "addr", "show", "wlan0"]) {
             Ok(output) => extract_ip_from_output(&output)
                 .unwrap_or_else(|| get_user_input("Could not auto-detect IP. Enter device IP address: ")),
             Err(_) => get_user_input("Enter device IP address: "),
@@ -846,20 +1925,14 @@ fn connect_wireless_legacy(state: &mut AppState) {
 
     println!("{} {}:{}...", "Connecting to".yellow(), ip.bright_white(), port.bright_white());
 
-    let result = Command::new("adb")
-        .arg("connect")
-        .arg(format!("{}:{}", ip, port))
-        .status();
-
-    if let Ok(status) = result {
-        if status.success() {
-            thread::sleep(Duration::from_secs(1));
-            if check_device_connected(state) {
-                println!("{} {}:{}", "Connected wirelessly to".green().bold(), ip, port);
-                state.connection = ConnectionType::Wireless;
-                state.connected_device = format!("{}:{}", ip, port);
-                return;
-            }
+    if state.backend.connect(&format!("{}:{}", ip, port)).is_ok() {
+        thread::sleep(Duration::from_secs(1));
+        state.serial = Some(format!("{}:{}", ip, port));
+        if check_device_connected(state) {
+            println!("{} {}:{}", "Connected wirelessly to".green().bold(), ip, port);
+            state.connection = ConnectionType::Wireless;
+            state.connected_device = format!("{}:{}", ip, port);
+            return;
         }
     }
 
@@ -901,7 +1974,7 @@ fn get_device_ip_automatically(state: &mut AppState) {
 
     println!("{}", "Detecting IP address...".yellow());
 
-    match execute_adb_command(&["shell", "ip", "addr", "show", "wlan0"]) {
+    match state.shell(&["ip", "addr", "show", "wlan0"]) {
         Ok(output) => {
             if let Some(ip) = extract_ip_from_output(&output) {
                 println!("{} {}", "Device IP Address:".green().bold(), ip.bright_white());
@@ -938,12 +2011,8 @@ fn enable_wireless_adb_on_device(state: &mut AppState) {
 
     println!("{}", "Enabling TCP/IP mode on port 5555...".yellow());
 
-    let result = Command::new("adb")
-        .args(&["tcpip", "5555"])
-        .status();
-
-    match result {
-        Ok(status) if status.success() => {
+    match state.backend.tcpip(state.target(), 5555) {
+        Ok(_) => {
             println!("{}", "Wireless debugging enabled!".green().bold());
             println!("\n{}", "Next steps:".cyan());
             println!("  1. Disconnect USB cable (optional)");
@@ -960,11 +2029,11 @@ fn disconnect_wireless(state: &mut AppState) {
     if state.connection == ConnectionType::Wireless {
         println!("{} {}...", "Disconnecting from".yellow(), state.connected_device);
 
-        let _ = Command::new("adb")
-            .arg("disconnect")
-            .arg(&state.connected_device)
-            .status();
+        let _ = state.backend.disconnect(&state.connected_device);
 
+        if state.serial.as_deref() == Some(state.connected_device.as_str()) {
+            state.serial = None;
+        }
         state.connection = ConnectionType::None;
         state.connected_device.clear();
 
@@ -974,28 +2043,23 @@ fn disconnect_wireless(state: &mut AppState) {
     }
 }
 
-fn list_connected_devices() {
+fn list_connected_devices(state: &AppState) -> bool {
     println!();
     println!("{}", "=========================================".cyan());
     println!("{}", "  Connected Devices".cyan().bold());
     println!("{}", "=========================================".cyan());
 
-    match execute_adb_command(&["devices", "-l"]) {
-        Ok(output) => {
+    match state.backend.devices() {
+        Ok(devices) => {
             let mut count = 0;
 
-            for (i, line) in output.lines().enumerate() {
-                if i > 0 && line.contains("\tdevice") && !line.contains("List of") {
-                    count += 1;
-
-                    if line.contains(':') {
-                        print!("{} ", "[Wireless]".green().bold());
-                    } else {
-                        print!("{} ", "[USB]     ".blue().bold());
-                    }
+            for device in devices.iter().filter(|d| d.is_ready()) {
+                count += 1;
+                print_device_line(device, device.serial == state.connected_device);
+            }
 
-                    println!("{}", line.bright_white());
-                }
+            for device in devices.iter().filter(|d| !d.is_ready()) {
+                println!("{} {} {}", "[Not ready]".red().bold(), device.serial.bright_white(), device.state.red());
             }
 
             println!("{}", "=========================================".cyan());
@@ -1004,94 +2068,956 @@ fn list_connected_devices() {
             } else {
                 println!("{} {}", "Total:".cyan(), format!("{} device(s)", count).bright_white());
             }
+            true
         }
         Err(e) => {
             println!("{} {}", "Error:".red(), e);
+            false
         }
     }
 }
 
 
-fn list_all_packages(state: &mut AppState) {
-    if !check_device_connected(state) {
-        println!("{}", "Error: No device connected!".red());
-        println!("Please connect a device first (USB or Wireless)");
-        return;
+fn print_device_line(device: &DeviceEntry, selected: bool) {
+    if device.is_wireless() {
+        print!("{} ", "[Wireless]".green().bold());
+    } else {
+        print!("{} ", "[USB]     ".blue().bold());
     }
 
-    println!("{}", "Fetching all packages from device...".yellow());
-
-    match execute_adb_command(&["shell", "pm", "list", "packages", "-s"]) {
-        Ok(output) => {
-            state.packages.clear();
-
-            for line in output.lines() {
-                if let Some(package_name) = line.strip_prefix("package:") {
-                    state.packages.push(Package {
-                        name: package_name.to_string(),
-                        is_system: true,
-                        is_selected: false,
-                    });
-                }
-            }
-
-            state.packages.sort_by(|a, b| a.name.cmp(&b.name));
+    let line = format!("{}  {}", device.serial, device.details);
+    if selected {
+        println!("{} {}", line.bright_white(), "(selected)".green());
+    } else {
+        println!("{}", line.bright_white());
+    }
+}
 
-            println!("{} {} system packages", 
-                "Found".green().bold(), 
-                state.packages.len().to_string().bright_white()
-            );
-            display_packages(&state.packages, None);
-        }
-        Err(e) => {
-            println!("{} {}", "Error executing ADB command:".red(), e);
+fn select_list_mode() -> Option<ListMode> {
+    println!();
+    println!("  1. System packages");
+    println!("  2. User-installed packages");
+    println!("  3. Disabled packages");
+    println!("  4. Enabled packages");
+    println!("  5. Uninstalled packages (already removed on this phone)");
+    println!("  6. Everything, with its state");
+
+    match get_user_input("Select listing: ").as_str() {
+        "1" => Some(ListMode::System),
+        "2" => Some(ListMode::ThirdParty),
+        "3" => Some(ListMode::Disabled),
+        "4" => Some(ListMode::Enabled),
+        "5" => Some(ListMode::Uninstalled),
+        "6" => Some(ListMode::All),
+        _ => {
+            println!("{}", "Invalid choice!".red());
+            None
         }
     }
 }
 
-fn load_bloatware_list(state: &mut AppState) {
+fn list_packages(state: &mut AppState, mode: ListMode) -> bool {
     if !check_device_connected(state) {
-        println!("{}", "Error: No device connected!".red());
-        return;
+        print_device_error(state);
+        println!("Please connect a device first (USB or Wireless)");
+        return false;
     }
 
-    println!("{}", "Scanning for common bloatware packages...".yellow());
+    println!("{} {}...", "Fetching".yellow(), mode.describe().yellow());
 
-    state.packages.clear();
-    let mut found_packages = HashSet::new();
+    let users = state.users();
+    let names = if users.len() > 1 { device_users(state).unwrap_or_default() } else { Vec::new() };
 
-    for bloatware in COMMON_BLOATWARE {
-        match execute_adb_command(&["shell", "pm", "list", "packages", bloatware]) {
-            Ok(output) => {
-                if output.contains(bloatware) {
-                    if found_packages.insert(bloatware.to_string()) {
-                        state.packages.push(Package {
-                            name: bloatware.to_string(),
-                            is_system: true,
-                            is_selected: false,
-                        });
-                    }
-                }
+    let mut listings = Vec::new();
+    for user in &users {
+        match user_listing(state, mode, *user) {
+            Ok(packages) => listings.push(packages),
+            Err(e) => {
+                println!("{} {}", "Error executing ADB command:".red(), e);
+                return false;
             }
-            Err(_) => {}
         }
     }
 
-    state.packages.sort_by(|a, b| a.name.cmp(&b.name));
-
-    println!("{} {} bloatware packages installed", 
-        "Found".green().bold(), 
-        state.packages.len().to_string().bright_white()
-    );
-    
-    if state.packages.is_empty() {
-        println!("{}", "Great! No common bloatware detected.".green());
-    } else {
-        display_packages(&state.packages, None);
+    // one labelling pass over everything listed, for any of the users
+    let mut merged = BTreeMap::new();
+    for package in listings.iter().flatten() {
+        merged.entry(package.name.clone()).or_insert_with(|| package.clone());
     }
+    let mut all: Vec<Package> = merged.into_values().collect();
+    label_packages(state, &mut all);
+    let labels: BTreeMap<&str, &Option<String>> = all.iter().map(|p| (p.name.as_str(), &p.label)).collect();
+
+    for (user, packages) in users.iter().zip(&mut listings) {
+        for package in packages.iter_mut() {
+            package.label = labels.get(package.name.as_str()).and_then(|l| (*l).clone());
+        }
+
+        if users.len() > 1 {
+            let name = names.iter().find(|u| u.id == *user).map(|u| u.name.as_str()).unwrap_or("unknown");
+            println!();
+            println!("{}", format!("User {} ({})", user, name).cyan().bold());
+        }
+        println!("{} {} {}", 
+            "Found".green().bold(), 
+            packages.len().to_string().bright_white(),
+            mode.describe()
+        );
+        if mode == ListMode::All {
+            display_package_table(state, packages);
+        } else {
+            display_packages(state, packages, None);
+        }
+    }
+
+    // interactive mode works on everything listed
+    state.packages = all;
+    true
+}
+
+// the packages `mode` selects for one user
+fn user_listing(state: &AppState, mode: ListMode, user: u32) -> Result<Vec<Package>, AdbError> {
+    let snapshot = state.user_packages(user)?;
+    Ok(snapshot.packages.values()
+        .filter(|p| mode.includes(p.state(), p.is_system))
+        .cloned()
+        .collect())
+}
+
+// loads the packages `mode` selects, for any of the selected users, into state.packages
+fn fetch_packages(state: &mut AppState, mode: ListMode) -> Result<(), AdbError> {
+    if !check_device_connected(state) {
+        return Err(state.device_error());
+    }
+
+    let mut packages = BTreeMap::new();
+    for user in state.users() {
+        for package in user_listing(state, mode, user)? {
+            packages.entry(package.name.clone()).or_insert(package);
+        }
+    }
+    state.packages = packages.into_values().collect();
+    Ok(())
+}
+
+fn load_bloatware_list(state: &mut AppState) -> bool {
+    if !check_device_connected(state) {
+        print_device_error(state);
+        return false;
+    }
+
+    println!("{} {} {}", "Scanning for".yellow(), state.bloat_list.len().to_string().bright_white(), "known bloatware packages...".yellow());
+
+    if let Err(e) = scan_bloatware(state) {
+        println!("{} {}", "Error:".red(), e);
+        return false;
+    }
+
+    let mut packages = std::mem::take(&mut state.packages);
+    label_packages(state, &mut packages);
+    state.packages = packages;
+
+    println!("{} {} bloatware packages installed", 
+        "Found".green().bold(), 
+        state.packages.len().to_string().bright_white()
+    );
+    
+    if state.packages.is_empty() {
+        println!("{}", "Great! No common bloatware detected.".green());
+    } else {
+        display_packages(state, &state.packages, None);
+    }
+    true
+}
+
+// loads the installed entries of the bloat list into state.packages
+fn scan_bloatware(state: &mut AppState) -> Result<(), AdbError> {
+    if !check_device_connected(state) {
+        return Err(state.device_error());
+    }
+
+    let snapshots = state.users().into_iter()
+        .map(|user| state.user_packages(user))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // unsafe entries are only there to warn about, they are not bloat
+    state.packages = state.bloat_list.entries()
+        .filter(|e| e.removal != Removal::Unsafe)
+        .filter_map(|e| snapshots.iter().find(|s| s.contains(&e.id)).map(|s| s.packages[&e.id].clone()))
+        .collect();
+    Ok(())
+}
+
+fn tier_color(removal: Removal, text: &str) -> ColoredString {
+    match removal {
+        Removal::Recommended => text.green(),
+        Removal::Advanced => text.yellow(),
+        Removal::Expert => text.bright_red(),
+        Removal::Unsafe => text.red().bold(),
+    }
+}
+
+fn tier_colored(removal: Removal) -> ColoredString {
+    tier_color(removal, removal.as_str())
+}
+
+// the package's tier when it is riskier than the user allows
+fn tier_above_max(state: &AppState, package_name: &str) -> Option<Removal> {
+    state.bloat_list.removal(package_name).filter(|r| *r > state.max_tier)
+}
+
+// extra confirmation for packages above the maximum tier, true to go ahead
+fn confirm_risky_packages(state: &AppState, packages: &[&str]) -> bool {
+    let risky: Vec<(&str, Removal)> = packages.iter()
+        .filter_map(|p| tier_above_max(state, p).map(|t| (*p, t)))
+        .collect();
+
+    if risky.is_empty() {
+        return true;
+    }
+
+    println!("{} {}", "WARNING: packages above your maximum safety tier:".red().bold(), tier_colored(state.max_tier));
+    for (name, tier) in &risky {
+        println!("  - {} [{}]", tier_color(*tier, name), tier_colored(*tier));
+    }
+    if risky.iter().any(|(_, t)| *t == Removal::Unsafe) {
+        println!("{}", "Unsafe packages can leave the device unusable (bootloop, no UI).".red().bold());
+    }
+
+    confirm_action("Remove them anyway?")
+}
+
+// the scripted counterpart of confirm_risky_packages, true when something is above the tier
+fn refuse_risky_packages(state: &AppState, packages: &[&str]) -> bool {
+    let risky: Vec<(&str, Removal)> = packages.iter()
+        .filter_map(|p| tier_above_max(state, p).map(|t| (*p, t)))
+        .collect();
+
+    if risky.is_empty() {
+        return false;
+    }
+
+    println!("{} {}", "Refusing to remove packages above the maximum tier".red().bold(), tier_colored(state.max_tier));
+    for (pkg, tier) in &risky {
+        println!("  - {} [{}]", pkg.red(), tier_colored(*tier));
+    }
+    println!("Raise it with --max-tier <tier> (or --force for any tier).");
+    true
+}
+
+fn device_users(state: &AppState) -> Result<Vec<DeviceUser>, AdbError> {
+    let output = state.shell(&["pm", "list", "users"])?;
+    Ok(users::parse_users(&output))
+}
+
+// every package of every user on the device, not only the selected ones
+fn capture_device_snapshot(state: &AppState) -> Result<DeviceSnapshot, AdbError> {
+    let mut users: Vec<u32> = device_users(state)?.iter().map(|u| u.id).collect();
+    if users.is_empty() {
+        users.push(0);
+    }
+
+    let mut snapshot = DeviceSnapshot::new(device_identity(state));
+    for user in users {
+        let packages = state.user_packages(user)?;
+        snapshot.users.insert(user, packages.packages.iter()
+            .map(|(name, pkg)| (name.clone(), pkg.state()))
+            .collect());
+    }
+    Ok(snapshot)
+}
+
+// saves a snapshot to `output`, or a new file in the snapshot dir
+fn take_snapshot(state: &mut AppState, output: Option<&Path>) -> bool {
+    if !check_device_connected(state) {
+        print_device_error(state);
+        return false;
+    }
+
+    let snapshot = match capture_device_snapshot(state) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            println!("{} {}", "Failed to read packages:".red(), e);
+            return false;
+        }
+    };
+    let path = match (output, &state.snapshot_dir) {
+        (Some(output), _) => output.to_path_buf(),
+        (None, Some(dir)) => snapshot::snapshot_path(dir),
+        (None, None) => {
+            println!("{} {}", "Error:".red(), snapshot::NO_SNAPSHOT_DIR);
+            return false;
+        }
+    };
+
+    if let Err(e) = snapshot.save(&path) {
+        println!("{} {}", "Error:".red(), e);
+        return false;
+    }
+
+    let count: usize = snapshot.users.values().map(|p| p.len()).sum();
+    println!("{} {}", "Snapshot saved:".green().bold(), path.display().to_string().bright_white());
+    println!("{} {} packages across {} user(s)", "Recorded:".cyan(), count, snapshot.users.len());
+    true
+}
+
+// compares `from` with the snapshot in `to`, or with the device when None.
+// false when they differ, so scripts can notice
+fn diff_snapshot(state: &mut AppState, from: &Path, to: Option<&Path>) -> bool {
+    let old = match DeviceSnapshot::load(from) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            println!("{} {}", "Error:".red(), e);
+            return false;
+        }
+    };
+
+    let new = match to {
+        Some(path) => DeviceSnapshot::load(path),
+        None if check_device_connected(state) => capture_device_snapshot(state).map_err(|e| e.to_string()),
+        None => Err(state.device_error().to_string()),
+    };
+    let new = match new {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            println!("{} {}", "Error:".red(), e);
+            return false;
+        }
+    };
+
+    let against = match to {
+        Some(path) => path.display().to_string(),
+        None => "the device".to_string(),
+    };
+    println!("{} {} ({}) {} {}", "Comparing".cyan(), from.display(), old.timestamp, "with".cyan(), against);
+    if let Some(reason) = identity_mismatches(&old.device, &new.device).first() {
+        println!("{} {}", "Note:".yellow(), reason.yellow());
+    }
+    for user in old.unmatched_users(&new) {
+        println!("{} {}", "Note:".yellow(), format!("user {} is only in one of them, not compared", user).yellow());
+    }
+
+    let differences = snapshot::diff(&old, &new);
+    if differences.is_empty() {
+        println!("{}", "No differences".green());
+        return true;
+    }
+
+    let several_users = old.users.len() > 1;
+    let mut last_user = None;
+    for difference in &differences {
+        if several_users && last_user != Some(difference.user) {
+            println!("{}", format!("User {}:", difference.user).cyan().bold());
+            last_user = Some(difference.user);
+        }
+        let change = format!("{:<10}", difference.change.describe());
+        let change = match difference.change {
+            snapshot::Change::Removed => change.red(),
+            snapshot::Change::Disabled => change.yellow(),
+            snapshot::Change::Enabled | snapshot::Change::Appeared => change.green(),
+        };
+        println!("  {} {}", change, difference.package.bright_white());
+    }
+
+    println!();
+    println!("{} {}", "Differences:".cyan(), differences.len().to_string().bright_white());
+    false
+}
+
+// makes the device match a profile, leaving alone whatever already does. the profile's
+// users apply for this run only. its tier can only lower --max-tier, `force` ignores both
+fn apply_profile(state: &mut AppState, path: &Path, backup: bool, force: bool) -> bool {
+    if !check_device_connected(state) {
+        print_device_error(state);
+        return false;
+    }
+
+    let profile = match Profile::load(path) {
+        Ok(profile) => profile,
+        Err(e) => {
+            println!("{} {}", "Error:".red(), e);
+            return false;
+        }
+    };
+
+    let saved = (state.users.clone(), state.all_users, state.max_tier);
+    match &profile.users {
+        Some(ProfileUsers::Ids(ids)) if !ids.is_empty() => {
+            state.users = ids.clone();
+            state.all_users = false;
+        }
+        Some(ProfileUsers::Keyword(_)) => state.all_users = true,
+        _ => {}
+    }
+    if force {
+        state.max_tier = Removal::Unsafe;
+    } else if let Some(tier) = profile.max_tier {
+        state.max_tier = state.max_tier.min(tier);
+    }
+
+    let name = if profile.name.is_empty() { path.display().to_string() } else { profile.name.clone() };
+    println!("{} {}", "Applying profile".cyan(), name.bright_white());
+    let ok = apply_profile_changes(state, &profile, backup);
+
+    (state.users, state.all_users, state.max_tier) = saved;
+    ok
+}
+
+fn apply_profile_changes(state: &mut AppState, profile: &Profile, backup: bool) -> bool {
+    // user, package, current state, what the profile wants
+    let mut changes: Vec<(u32, String, PackageState, Desired)> = Vec::new();
+    for user in state.users() {
+        let snapshot = match state.user_packages(user) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                println!("{} {}", "Failed to read packages:".red(), e);
+                return false;
+            }
+        };
+
+        for (name, desired) in profile.resolve(snapshot.packages.keys()) {
+            let current = snapshot.packages.get(&name).map(Package::state);
+            if desired.is_satisfied(current) {
+                continue;
+            }
+            match current {
+                Some(current) => changes.push((user, name, current, desired)),
+                None => println!("{} {} {}", "Not on this device:".bright_black(), name,
+                    format!("(user {}, wanted {})", user, desired.as_str()).bright_black()),
+            }
+        }
+    }
+
+    if changes.is_empty() {
+        println!("{}", "In sync: nothing to change".green().bold());
+        return true;
+    }
+
+    println!();
+    println!("{}", "Changes:".cyan().bold());
+    for (user, name, _, desired) in &changes {
+        let action = match desired {
+            Desired::Removed => format!("{:<8}", "remove").red(),
+            Desired::Disabled => format!("{:<8}", "disable").yellow(),
+            Desired::Enabled => format!("{:<8}", "enable").green(),
+        };
+        println!("  {} {} {}", action, name.bright_white(), format!("(user {})", user).bright_black());
+    }
+    println!();
+
+    let mut removals: Vec<String> = changes.iter()
+        .filter(|(_, _, _, desired)| *desired != Desired::Enabled)
+        .map(|(_, name, _, _)| name.clone())
+        .collect();
+    removals.sort();
+    removals.dedup();
+    let names: Vec<&str> = removals.iter().map(String::as_str).collect();
+    if state.interactive {
+        if !confirm_risky_packages(state, &names) || !confirm_action("Apply these changes?") {
+            println!("{}", "Cancelled".yellow());
+            return false;
+        }
+    } else if refuse_risky_packages(state, &names) {
+        return false;
+    }
+
+    let mut backup_path = None;
+    if backup && !removals.is_empty() {
+        match create_backup_auto(state, &removals) {
+            Ok(path) => backup_path = Some(path),
+            Err(e) => {
+                println!("{} {}", "Backup failed:".red(), e);
+                println!("Use --no-backup to apply without a backup.");
+                return false;
+            }
+        }
+    }
+
+    let mut results = Vec::new();
+    let mut failed = 0;
+    state.start_session();
+    for (user, name, current, desired) in &changes {
+        let ok = match desired {
+            Desired::Removed => {
+                let result = remove_package(state, name, *user);
+                let ok = result.outcome.is_success();
+                results.push(result);
+                ok
+            }
+            Desired::Disabled => {
+                let kept = PackageState { installed: true, enabled: false };
+                let outcome = restore_package_state(state, name, *user, kept, None)
+                    .and_then(|_| if current.enabled { disable_package(state, name, *user) } else { Ok(()) });
+                report_profile_step(state, "disable", name, &outcome);
+                results.push(RemovalResult {
+                    package: name.clone(),
+                    user: *user,
+                    outcome: match &outcome {
+                        Ok(()) if state.dry_run => RemovalOutcome::Skipped,
+                        Ok(()) => RemovalOutcome::Disabled,
+                        Err(e) => RemovalOutcome::Failed(e.to_string()),
+                    },
+                });
+                outcome.is_ok()
+            }
+            Desired::Enabled => {
+                let outcome = restore_package_state(state, name, *user, PackageState::ACTIVE, None).map(|_| ());
+                report_profile_step(state, "enable", name, &outcome);
+                outcome.is_ok()
+            }
+        };
+        if !ok {
+            failed += 1;
+        }
+    }
+    state.end_session();
+
+    if let Some(path) = &backup_path {
+        if !state.dry_run {
+            if let Err(e) = record_removal_outcomes(Path::new(path), &results) {
+                println!("{} {}", "Could not record results in backup:".red(), e);
+            }
+        }
+    }
+
+    println!();
+    if state.dry_run {
+        println!("{}", "Dry run: nothing was changed".magenta());
+    } else if failed == 0 {
+        println!("{} {} change(s)", "Profile applied:".green().bold(), changes.len());
+    } else {
+        println!("{} {} of {} change(s) failed", "Profile partly applied:".red().bold(), failed, changes.len());
+    }
+    failed == 0
+}
+
+// what the selected users have removed or disabled, as a profile another phone can apply.
+// packages the users disagree on are left out. the tier is left to whoever applies it
+fn export_profile(state: &mut AppState, output: &Path) -> bool {
+    if !check_device_connected(state) {
+        print_device_error(state);
+        return false;
+    }
+
+    let users = state.users();
+    let mut states: BTreeMap<String, Vec<Option<Desired>>> = BTreeMap::new();
+    for user in &users {
+        let snapshot = match state.user_packages(*user) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                println!("{} {}", "Failed to read packages:".red(), e);
+                return false;
+            }
+        };
+        for (name, pkg) in &snapshot.packages {
+            let desired = match (pkg.installed, pkg.enabled) {
+                (false, _) => Some(Desired::Removed),
+                (true, false) => Some(Desired::Disabled),
+                (true, true) => None,
+            };
+            states.entry(name.clone()).or_default().push(desired);
+        }
+    }
+
+    let mut entries = Vec::new();
+    let mut risky = Vec::new();
+    for (name, wanted) in states {
+        let first = wanted[0];
+        if wanted.len() != users.len() || wanted.iter().any(|w| *w != first) {
+            if wanted.iter().any(Option::is_some) {
+                println!("{} {} {}", "Left out".bright_black(), name, "(differs between users)".bright_black());
+            }
+            continue;
+        }
+        let Some(desired) = first else { continue };
+
+        let known = state.bloat_list.get(&name);
+        if let Some(entry) = known.filter(|e| e.removal > Removal::Recommended) {
+            risky.push((name.clone(), entry.removal));
+        }
+        entries.push(ProfileEntry {
+            id: name,
+            state: desired,
+            description: known.map(|e| e.description.clone()).unwrap_or_default(),
+        });
+    }
+
+    let identity = device_identity(state);
+    let model = if identity.model.is_empty() { state.connected_device.clone() } else { identity.model };
+    let profile = Profile {
+        name: format!("{} ({})", model, chrono::Local::now().format("%Y-%m-%d")),
+        users: if users == [0] { None } else { Some(ProfileUsers::Ids(users)) },
+        max_tier: None,
+        packages: entries,
+    };
+
+    if let Err(e) = profile.save(output) {
+        println!("{} {}", "Error:".red(), e);
+        return false;
+    }
+
+    let count = |d: Desired| profile.packages.iter().filter(|e| e.state == d).count();
+    println!("{} {}", "Profile saved:".green().bold(), output.display().to_string().bright_white());
+    println!("{} {} removed, {} disabled", "Recorded:".cyan(), count(Desired::Removed), count(Desired::Disabled));
+    if !risky.is_empty() {
+        println!("{} {}", "Warning:".yellow().bold(), "these are above the recommended tier, applying them needs --max-tier or --force:".yellow());
+        for (name, tier) in &risky {
+            println!("  {} {}", name, format!("({})", tier.as_str()).bright_black());
+        }
+    }
+    true
+}
+
+fn report_profile_step(state: &AppState, action: &str, name: &str, outcome: &Result<(), AdbError>) {
+    match outcome {
+        Ok(()) if state.dry_run => {}
+        Ok(()) => println!("{} {} {}", "Successfully".green().bold(), format!("{}d:", action).green().bold(), name),
+        Err(e) => println!("{} {} {}", format!("Failed to {}:", action).red(), name, e.to_string().bright_black()),
+    }
+}
+
+fn disable_package(state: &AppState, package_name: &str, user: u32) -> Result<(), AdbError> {
+    let user_arg = user.to_string();
+    let disable = ["pm", "disable-user", "--user", &user_arg, package_name];
+    if state.dry_run {
+        print_dry_run(state, &disable);
+        return Ok(());
+    }
+    state.invalidate_packages();
+    state.shell(&disable).map(|_| ())
 }
 
-fn display_packages(packages: &[Package], filter: Option<&str>) {
+// lists the saved snapshots and asks for one
+fn choose_snapshot(state: &AppState, prompt: &str) -> Option<PathBuf> {
+    let Some(dir) = &state.snapshot_dir else {
+        println!("{} {}", "Error:".red(), snapshot::NO_SNAPSHOT_DIR);
+        return None;
+    };
+    let snapshots = snapshot::find_snapshots(dir);
+    if snapshots.is_empty() {
+        println!("{} {}", "No snapshots found in".yellow(), dir.display());
+        return None;
+    }
+
+    println!();
+    for (i, path) in snapshots.iter().enumerate() {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("unknown");
+        println!("{} {}", format!("[{}]", i + 1).bright_black(), name.bright_white());
+    }
+    println!();
+
+    match get_user_input(prompt).parse::<usize>() {
+        Ok(n) if n > 0 && n <= snapshots.len() => Some(snapshots[n - 1].clone()),
+        Ok(0) => {
+            println!("{}", "Cancelled".yellow());
+            None
+        }
+        _ => {
+            println!("{}", "Invalid choice".red());
+            None
+        }
+    }
+}
+
+// picks a saved snapshot and compares the device with it
+fn compare_with_snapshot(state: &mut AppState) {
+    if let Some(path) = choose_snapshot(state, "Enter snapshot number to compare with (or 0 to cancel): ") {
+        diff_snapshot(state, &path, None);
+    }
+}
+
+// brings the device back to the package states in a snapshot, asking first when `confirm`
+// is set. the state it had before is saved as a new snapshot, so a revert can be reverted
+fn revert_to_snapshot(state: &mut AppState, path: &Path, confirm: bool) -> bool {
+    if !check_device_connected(state) {
+        print_device_error(state);
+        return false;
+    }
+
+    let target = match DeviceSnapshot::load(path) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            println!("{} {}", "Error:".red(), e);
+            return false;
+        }
+    };
+    let current = match capture_device_snapshot(state) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            println!("{} {}", "Failed to read packages:".red(), e);
+            return false;
+        }
+    };
+
+    println!("{} {} ({})", "Reverting to".cyan(), path.display(), target.timestamp);
+    let mismatches = identity_mismatches(&target.device, &current.device);
+    if !mismatches.is_empty() {
+        println!("{}", "Warning: this snapshot may not match the connected device".yellow().bold());
+        for mismatch in &mismatches {
+            println!("  - {}", mismatch.yellow());
+        }
+    }
+    for user in target.unmatched_users(&current) {
+        println!("{} {}", "Note:".yellow(), format!("user {} is only on one side, left alone", user).yellow());
+    }
+
+    let plan = snapshot::plan(&target, &current);
+    for (user, package) in &plan.unavailable {
+        println!("{} {} {}", "Cannot reinstall".yellow(), package.bright_white(),
+            format!("(user {}, the device no longer has it)", user).bright_black());
+    }
+    if plan.steps.is_empty() {
+        println!("{}", "The device already matches the snapshot".green());
+        return plan.unavailable.is_empty();
+    }
+
+    println!();
+    println!("{}", "Plan:".cyan().bold());
+    for step in &plan.steps {
+        let operation = format!("{:<10}", step.operation.describe());
+        let operation = match step.operation {
+            snapshot::Operation::Uninstall | snapshot::Operation::Disable => operation.red(),
+            snapshot::Operation::Enable | snapshot::Operation::InstallExisting => operation.green(),
+        };
+        println!("  {} {} {}", operation, step.package.bright_white(), format!("(user {})", step.user).bright_black());
+    }
+    println!("{} {}", "Steps:".cyan(), plan.steps.len().to_string().bright_white());
+    println!();
+
+    let removals: Vec<&str> = plan.steps.iter()
+        .filter(|s| matches!(s.operation, snapshot::Operation::Uninstall | snapshot::Operation::Disable))
+        .map(|s| s.package.as_str())
+        .collect();
+    if state.interactive {
+        if !confirm_risky_packages(state, &removals) {
+            println!("{}", "Cancelled".yellow());
+            return false;
+        }
+    } else if refuse_risky_packages(state, &removals) {
+        return false;
+    }
+    if confirm && !confirm_action("Apply this plan?") {
+        println!("{}", "Cancelled".yellow());
+        return false;
+    }
+
+    if !state.dry_run {
+        let Some(dir) = &state.snapshot_dir else {
+            println!("{} {}", "Error:".red(), snapshot::NO_SNAPSHOT_DIR);
+            return false;
+        };
+        let before = snapshot::snapshot_path(dir);
+        match current.save(&before) {
+            Ok(()) => println!("{} {}", "Saved the current state to".cyan(), before.display()),
+            Err(e) => {
+                println!("{} {}", "Error:".red(), e);
+                return false;
+            }
+        }
+    }
+
+    let mut failed = 0;
+    state.start_session();
+    for step in &plan.steps {
+        let user = step.user.to_string();
+        let command = step.operation.command(&step.package, &user);
+        if state.dry_run {
+            print_dry_run(state, &command);
+            continue;
+        }
+
+        print!("{} {} {}...", step.operation.describe().yellow(), step.package.bright_white(), format!("(user {})", step.user).bright_black());
+        io::stdout().flush().ok();
+        state.invalidate_packages();
+        match state.shell(&command) {
+            Ok(_) => println!(" {}", "OK".green()),
+            Err(e) => {
+                println!(" {} {}", "FAILED".red(), e.to_string().bright_black());
+                failed += 1;
+            }
+        }
+    }
+    state.end_session();
+
+    if state.dry_run {
+        println!("{}", "Dry run: nothing was changed".magenta());
+    } else {
+        println!();
+        println!("{} {} of {} steps", "Reverted:".green().bold(), plan.steps.len() - failed, plan.steps.len());
+    }
+    failed == 0 && plan.unavailable.is_empty()
+}
+
+fn list_users(state: &mut AppState) -> bool {
+    if !check_device_connected(state) {
+        print_device_error(state);
+        return false;
+    }
+
+    match device_users(state) {
+        Ok(users) => {
+            for user in &users {
+                let running = if user.running { "running" } else { "stopped" };
+                println!("{:>3}  {}  {}", user.id, user.name.bright_white(), running.bright_black());
+            }
+            true
+        }
+        Err(e) => {
+            println!("{} {}", "Could not list users:".red(), e);
+            false
+        }
+    }
+}
+
+fn select_users(state: &mut AppState) {
+    if !check_device_connected(state) {
+        print_device_error(state);
+        return;
+    }
+
+    let users = match device_users(state) {
+        Ok(users) => users,
+        Err(e) => {
+            println!("{} {}", "Could not list users:".red(), e);
+            return;
+        }
+    };
+
+    println!();
+    println!("{}", "Users and profiles on this device:".cyan().bold());
+    for user in &users {
+        let selected = state.all_users || state.users.contains(&user.id);
+        let mark = if selected { "[X]".green().bold() } else { "[ ]".normal() };
+        let running = if user.running { "" } else { " (not running)" };
+        println!("{} {:>3}  {}{}", mark, user.id, user.name.bright_white(), running.bright_black());
+    }
+
+    let input = get_user_input("User ids separated by commas, or 'all': ");
+    if input.is_empty() {
+        println!("{}", "Unchanged".yellow());
+        return;
+    }
+    if input.eq_ignore_ascii_case("all") {
+        state.all_users = true;
+        println!("{}", "Working on all users".green());
+        return;
+    }
+
+    let mut ids = Vec::new();
+    for part in input.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match part.parse::<u32>() {
+            Ok(id) if users.iter().any(|u| u.id == id) => {
+                if !ids.contains(&id) {
+                    ids.push(id);
+                }
+            }
+            _ => {
+                println!("{} {}", "Unknown user:".red(), part);
+                return;
+            }
+        }
+    }
+    // only separators, like ","
+    if ids.is_empty() {
+        println!("{}", "Unchanged".yellow());
+        return;
+    }
+
+    state.all_users = false;
+    state.users = ids;
+    println!("{} {}", "Working on users:".green(), input.bright_white());
+}
+
+fn select_max_tier(state: &mut AppState) {
+    println!();
+    println!("{} {}", "Current maximum tier:".cyan(), tier_colored(state.max_tier));
+    println!("  1. {} - safe to remove for everyone", tier_colored(Removal::Recommended));
+    println!("  2. {} - may break a feature you use", tier_colored(Removal::Advanced));
+    println!("  3. {} - only if you know what it does", tier_colored(Removal::Expert));
+    println!("  4. {} - can break the device", tier_colored(Removal::Unsafe));
+
+    let tier = match get_user_input("Select tier: ").as_str() {
+        "1" => Removal::Recommended,
+        "2" => Removal::Advanced,
+        "3" => Removal::Expert,
+        "4" => Removal::Unsafe,
+        _ => {
+            println!("{}", "Unchanged".yellow());
+            return;
+        }
+    };
+
+    state.max_tier = tier;
+    println!("{} {}", "Maximum tier set to".green(), tier_colored(tier));
+}
+
+// fills in app labels from the cache, pulling the apks of packages not seen before.
+// reading a label means transferring the whole apk, so this only runs with --read-labels
+fn label_packages(state: &AppState, packages: &mut [Package]) {
+    if !state.read_labels {
+        return;
+    }
+
+    let fingerprint = state.shell(&["getprop", "ro.build.fingerprint"])
+        .map(|f| f.trim().to_string())
+        .unwrap_or_default();
+    let mut cache = LabelCache::load(state.label_dir.as_deref(), &fingerprint);
+
+    let missing: Vec<usize> = (0..packages.len())
+        .filter(|&i| cache.get(&packages[i].name).is_none() && !packages[i].apk_path.is_empty())
+        .collect();
+
+    if !missing.is_empty() {
+        println!("{} {} {}", "Reading app labels for".yellow(), missing.len().to_string().bright_white(),
+            "packages (cached for next time)...".yellow());
+        let local = env::temp_dir().join(format!("android_debloater_{}.apk", process::id()));
+
+        for (done, &i) in missing.iter().enumerate() {
+            print!("\r  {}/{}", done + 1, missing.len());
+            let _ = io::stdout().flush();
+            // a failed pull is tried again next time, an unreadable apk is not
+            if let Ok(label) = pull_label(state, &packages[i].apk_path, &local) {
+                cache.insert(&packages[i].name, label.unwrap_or_default());
+            }
+        }
+        println!();
+
+        let _ = fs::remove_file(&local);
+        if let Err(e) = cache.save() {
+            println!("{} {}", "Could not cache app labels:".yellow(), e);
+        }
+    }
+
+    for package in packages.iter_mut() {
+        package.label = cache.get(&package.name)
+            .filter(|l| !l.is_empty())
+            .map(str::to_string);
+    }
+}
+
+fn pull_label(state: &AppState, remote: &str, local: &Path) -> Result<Option<String>, String> {
+    state.backend.pull(state.target(), remote, local).map_err(|e| state.record_error(e).to_string())?;
+    let apk = fs::read(local).map_err(|e| e.to_string())?;
+    Ok(apk::read_label(&apk).unwrap_or(None))
+}
+
+// "system, v34, from com.android.vending, uid 10123, /system/app/Foo/Foo.apk"
+fn describe_package(package: &Package) -> String {
+    let mut parts = vec![if package.is_system { "system".to_string() } else { "user".to_string() }];
+    if let Some(code) = package.version_code {
+        parts.push(format!("v{}", code));
+    }
+    if let Some(installer) = &package.installer {
+        parts.push(format!("from {}", installer));
+    }
+    if let Some(uid) = package.uid {
+        parts.push(format!("uid {}", uid));
+    }
+    parts.push(package.apk_path.clone());
+    parts.join(", ")
+}
+
+fn display_packages(state: &AppState, packages: &[Package], filter: Option<&str>) {
     println!();
     println!("{}", "===========================================".blue());
     println!("{}", "           Package List".blue().bold());
@@ -1099,17 +3025,44 @@ fn display_packages(packages: &[Package], filter: Option<&str>) {
 
     let mut displayed = 0;
     for (i, package) in packages.iter().enumerate() {
-        if filter.is_none() || package.name.to_lowercase().contains(&filter.unwrap().to_lowercase()) {
+        if filter.is_none_or(|f| package.matches(f)) {
             let checkbox = if package.is_selected { "[X]".green().bold() } else { "[ ]".normal() };
             let number = format!("[{:3}]", i + 1).bright_black();
             
-            let package_display = if is_critical_package(&package.name) {
-                format!("{} (CRITICAL)", package.name).red()
-            } else {
-                package.name.normal()
+            let package_state = match package.state_label() {
+                Some("uninstalled") => " (uninstalled)".red(),
+                Some(label) => format!(" ({})", label).yellow(),
+                None => "".normal(),
             };
-            
-            println!("{} {} {}", checkbox, number, package_display);
+
+            let label = match &package.label {
+                Some(label) => format!("{} ", label.bold()),
+                None => String::new(),
+            };
+
+            match state.bloat_list.get(&package.name) {
+                Some(entry) => {
+                    let name = format!("{}{}", label, tier_color(entry.removal, &package.name));
+                    let marker = if entry.removal > state.max_tier { "!".red().bold() } else { " ".normal() };
+                    let about = if entry.vendor.is_empty() {
+                        entry.description.clone()
+                    } else {
+                        format!("{} ({})", entry.description, entry.vendor)
+                    };
+                    println!("{} {}{} {}{} [{}]  {}", checkbox, number, marker, name, package_state,
+                        tier_colored(entry.removal), about.bright_black());
+                    if !entry.notes.is_empty() {
+                        println!("            {} {}", "note:".yellow(), entry.notes.yellow());
+                    }
+                    if !entry.needed_by.is_empty() {
+                        println!("            {} {}", "needed by:".yellow(), entry.needed_by.join(", ").yellow());
+                    }
+                }
+                None => println!("{} {}  {}{}{}", checkbox, number, label, package.name, package_state),
+            }
+            if !package.apk_path.is_empty() {
+                println!("            {}", describe_package(package).bright_black());
+            }
             displayed += 1;
         }
     }
@@ -1119,9 +3072,40 @@ fn display_packages(packages: &[Package], filter: Option<&str>) {
     println!();
 }
 
+// the combined view: one row per package with its state
+fn display_package_table(state: &AppState, packages: &[Package]) {
+    println!();
+    println!("{}", format!("{:<12} {:<7} {:<11} {}", "STATE", "TYPE", "TIER", "PACKAGE").blue().bold());
+
+    for package in packages {
+        let package_state = match package.state_label() {
+            Some("uninstalled") => format!("{:<12}", "uninstalled").red(),
+            Some(label) => format!("{:<12}", label).yellow(),
+            None => format!("{:<12}", "enabled").green(),
+        };
+        let kind = if package.is_system { "system" } else { "user" };
+        let tier = match state.bloat_list.removal(&package.name) {
+            Some(removal) => tier_color(removal, &format!("{:<11}", removal.as_str())),
+            None => format!("{:<11}", "-").bright_black(),
+        };
+        let label = match &package.label {
+            Some(label) => format!("  {}", label.bright_black()),
+            None => String::new(),
+        };
+
+        println!("{} {:<7} {} {}{}", package_state, kind, tier, package.name, label);
+    }
+
+    println!("{}", "===========================================".blue());
+    let count = |label: Option<&str>| packages.iter().filter(|p| p.state_label() == label).count();
+    println!("{} {} enabled, {} disabled, {} uninstalled", "Total:".cyan(),
+        count(None), count(Some("disabled")), count(Some("uninstalled")));
+    println!();
+}
+
 fn interactive_mode(state: &mut AppState) {
     if !check_device_connected(state) {
-        println!("{}", "Error: No device connected!".red());
+        print_device_error(state);
         return;
     }
 
@@ -1131,7 +3115,7 @@ fn interactive_mode(state: &mut AppState) {
     }
 
     loop {
-        display_packages(&state.packages, None);
+        display_packages(state, &state.packages, None);
 
         println!("{}", "Interactive Mode:".yellow().bold());
         println!("Enter package number to toggle selection, or:");
@@ -1166,7 +3150,7 @@ fn interactive_mode(state: &mut AppState) {
                     thread::sleep(Duration::from_millis(500));
                 } else if choice == count + 3 {
                     let filter = get_user_input("Enter search term: ");
-                    display_packages(&state.packages, Some(&filter));
+                    display_packages(state, &state.packages, Some(&filter));
                     println!("{}", "Press Enter to continue...".bright_black());
                     let _ = get_user_input("");
                 } else if choice == count + 4 {
@@ -1180,18 +3164,9 @@ fn interactive_mode(state: &mut AppState) {
                         continue;
                     }
 
-                    let critical_selected: Vec<_> = selected.iter()
-                        .filter(|p| is_critical_package(&p.name))
-                        .collect();
-
-                    if !critical_selected.is_empty() {
-                        println!("{}", "WARNING: Critical system packages selected!".red().bold());
-                        for pkg in &critical_selected {
-                            println!("  - {}", pkg.name.red());
-                        }
-                        if !confirm_action("This may cause system instability. Continue?") {
-                            continue;
-                        }
+                    let selected_names: Vec<&str> = selected.iter().map(|p| p.name.as_str()).collect();
+                    if !confirm_risky_packages(state, &selected_names) {
+                        continue;
                     }
 
                     if confirm_action(&format!("Remove {} packages?", selected.len())) {
@@ -1199,25 +3174,29 @@ fn interactive_mode(state: &mut AppState) {
                         println!("{}", "Creating backup before removal...".yellow());
                         let package_names: Vec<String> = selected.iter().map(|p| p.name.clone()).collect();
                         
-                        match create_backup_auto(&package_names) {
+                        let backup_path = match create_backup_auto(state, &package_names) {
                             Ok(backup_path) => {
-                                println!("{} {}", "Backup saved:".green(), backup_path.bright_white());
+                                if !state.dry_run {
+                                    println!("{} {}", "Backup saved:".green(), backup_path.bright_white());
+                                }
+                                Some(backup_path)
                             }
                             Err(e) => {
                                 println!("{} {}", "Backup failed:".red(), e);
                                 if !confirm_action("Continue without backup?") {
                                     continue;
                                 }
+                                None
                             }
-                        }
+                        };
                         
                         println!();
-                        for package in selected {
-                            remove_package(&package.name);
-                        }
-                        println!("{}", "\nOperation completed!".green().bold());
-                        show_temp_location();
-                        thread::sleep(Duration::from_secs(2));
+                        let results = remove_for_users(state, &package_names);
+
+                        finish_removal(state, backup_path.as_deref(), &results);
+                        drop_missing_packages(state);
+                        println!("{}", "Press Enter to continue...".bright_black());
+                        let _ = get_user_input("");
                     }
                 } else if choice == count + 5 {
                     break;
@@ -1228,9 +3207,21 @@ fn interactive_mode(state: &mut AppState) {
     }
 }
 
+// takes packages that are no longer installed out of the loaded list
+fn drop_missing_packages(state: &mut AppState) {
+    if state.dry_run {
+        return;
+    }
+
+    match state.installed_packages() {
+        Ok(snapshot) => state.packages.retain(|p| snapshot.contains(&p.name)),
+        Err(e) => println!("{} {}", "Could not refresh the package list:".red(), e),
+    }
+}
+
 fn remove_single_package(state: &mut AppState) {
     if !check_device_connected(state) {
-        println!("{}", "Error: No device connected!".red());
+        print_device_error(state);
         return;
     }
 
@@ -1241,67 +3232,200 @@ fn remove_single_package(state: &mut AppState) {
         return;
     }
 
-    if is_critical_package(&package_name) {
-        println!("{}", "WARNING: This is a critical system package!".red().bold());
-        if !confirm_action("Removing it may cause system instability. Continue?") {
-            println!("Cancelled.");
-            return;
-        }
+    if !confirm_risky_packages(state, &[package_name.as_str()]) {
+        println!("Cancelled.");
+        return;
     }
 
     // auto backup
     println!("{}", "Creating backup...".yellow());
-    match create_backup_auto(&vec![package_name.clone()]) {
+    let backup_path = match create_backup_auto(state, std::slice::from_ref(&package_name)) {
         Ok(backup_path) => {
-            println!("{} {}", "Backup saved:".green(), backup_path.bright_white());
+            if !state.dry_run {
+                println!("{} {}", "Backup saved:".green(), backup_path.bright_white());
+            }
+            Some(backup_path)
         }
         Err(e) => {
             println!("{} {}", "Backup failed:".red(), e);
             if !confirm_action("Continue without backup?") {
                 return;
             }
+            None
         }
+    };
+
+    let results = remove_for_users(state, std::slice::from_ref(&package_name));
+    finish_removal(state, backup_path.as_deref(), &results);
+}
+
+// non-interactive removal used by the command line
+fn remove_packages(state: &mut AppState, packages: &[String], backup: bool) -> bool {
+    if !check_device_connected(state) {
+        print_device_error(state);
+        return false;
+    }
+
+    // no prompts here, anything above the tier is refused
+    let names: Vec<&str> = packages.iter().map(String::as_str).collect();
+    if refuse_risky_packages(state, &names) {
+        return false;
     }
 
-    remove_package(&package_name);
-    show_temp_location();
+    let mut backup_path = None;
+    if backup {
+        println!("{}", "Creating backup...".yellow());
+        match create_backup_auto(state, packages) {
+            Ok(path) => {
+                if !state.dry_run {
+                    println!("{} {}", "Backup saved:".green(), path.bright_white());
+                }
+                backup_path = Some(path);
+            }
+            Err(e) => {
+                println!("{} {}", "Backup failed:".red(), e);
+                println!("Use --no-backup to remove without a backup.");
+                return false;
+            }
+        }
+    }
+
+    let results = remove_for_users(state, packages);
+
+    finish_removal(state, backup_path.as_deref(), &results);
+    results.iter().all(|r| r.outcome.is_success())
 }
 
-fn remove_package(package_name: &str) {
-    println!("{} {}", "Removing package:".yellow(), package_name.bright_white());
+// removes the packages for every selected user. with several users a package
+// is only touched for the users that have it installed
+fn remove_for_users(state: &AppState, packages: &[String]) -> Vec<RemovalResult> {
+    let users = state.users();
+    let snapshots: Vec<Option<Rc<PackageSnapshot>>> = if users.len() > 1 {
+        users.iter().map(|u| state.user_packages(*u).ok()).collect()
+    } else {
+        Vec::new()
+    };
 
-    let result = Command::new("adb")
-        .args(&["shell", "pm", "uninstall", "--user", "0", package_name])
-        .output();
+    let mut results = Vec::new();
+    state.start_session();
+    for (i, user) in users.iter().enumerate() {
+        if users.len() > 1 {
+            println!("{}", format!("User {}:", user).cyan().bold());
+        }
 
-    match result {
-        Ok(output) if output.status.success() => {
-            println!("{} {}", "Successfully removed:".green().bold(), package_name);
+        for package in packages {
+            let skip = snapshots.get(i).and_then(Option::as_ref).is_some_and(|own| !own.contains(package))
+                && snapshots.iter().flatten().any(|other| other.contains(package));
+            if skip {
+                println!("{} {} {}", "Skipping".bright_black(), package.bright_white(), "(not installed for this user)".bright_black());
+                continue;
+            }
+            results.push(remove_package(state, package, *user));
         }
-        _ => {
-            println!("{} {}", "Failed to remove:".red(), package_name);
+    }
+    state.end_session();
+    results
+}
 
-            println!("{}", "Trying to disable instead...".yellow());
+// tries `pm uninstall`, falls back to `pm disable-user`
+fn remove_package(state: &AppState, package_name: &str, user: u32) -> RemovalResult {
+    println!("{} {}", "Removing package:".yellow(), package_name.bright_white());
+    let user_arg = user.to_string();
+    let uninstall = ["pm", "uninstall", "--user", &user_arg, package_name];
+    let disable = ["pm", "disable-user", "--user", &user_arg, package_name];
+
+    let outcome = if state.dry_run {
+        print_dry_run(state, &uninstall);
+        println!("{}", "  if that fails:".bright_black());
+        print_dry_run(state, &disable);
+        RemovalOutcome::Skipped
+    } else {
+        state.invalidate_packages();
+        match state.shell(&uninstall) {
+            Ok(_) => {
+                println!("{} {}", "Successfully removed:".green().bold(), package_name);
+                RemovalOutcome::Uninstalled
+            }
+            // the phone went away, disabling would fail the same way
+            Err(e) if e.is_connection() => {
+                println!("{} {} {}", "Failed to remove:".red(), package_name, e.to_string().bright_black());
+                RemovalOutcome::Failed(e.to_string())
+            }
+            Err(uninstall_error) => {
+                println!("{} {} {}", "Failed to remove:".red(), package_name, uninstall_error.to_string().bright_black());
 
-            let disable_result = Command::new("adb")
-                .args(&["shell", "pm", "disable-user", "--user", "0", package_name])
-                .output();
+                println!("{}", "Trying to disable instead...".yellow());
 
-            match disable_result {
-                Ok(output) if output.status.success() => {
-                    println!("{} {}", "Successfully disabled:".green().bold(), package_name);
-                }
-                _ => {
-                    println!("{} {}", "Failed to disable:".red(), package_name);
+                match state.shell(&disable) {
+                    Ok(_) => {
+                        println!("{} {}", "Successfully disabled:".green().bold(), package_name);
+                        RemovalOutcome::Disabled
+                    }
+                    Err(disable_error) => {
+                        println!("{} {} {}", "Failed to disable:".red(), package_name, disable_error.to_string().bright_black());
+                        RemovalOutcome::Failed(format!("{} / {}", uninstall_error, disable_error))
+                    }
                 }
             }
         }
+    };
+
+    RemovalResult {
+        package: package_name.to_string(),
+        user,
+        outcome,
+    }
+}
+
+// None when the device has never heard of the package
+fn query_package_state(state: &AppState, package_name: &str, user: u32) -> Result<Option<PackageState>, AdbError> {
+    let has = |output: String| output.lines().any(|l| l.strip_prefix("package:") == Some(package_name));
+    let user_arg = user.to_string();
+    let list = |flags: &[&str]| {
+        let mut args = vec!["pm", "list", "packages"];
+        args.extend_from_slice(flags);
+        args.extend(["--user", &user_arg, package_name]);
+        state.shell(&args)
+    };
+
+    if !has(list(&["-u"])?) {
+        return Ok(None);
+    }
+
+    let installed = has(list(&[])?);
+    let disabled = has(list(&["-d", "-u"])?);
+
+    Ok(Some(PackageState { installed, enabled: !disabled }))
+}
+
+// brings a package back to `target`, picking install-existing and/or pm enable from its
+// current state. `hint` is what the removal did, used when the state query fails
+fn restore_package_state(state: &AppState, package_name: &str, user: u32, target: PackageState, hint: Option<&RemovalOutcome>) -> Result<RestoreAction, AdbError> {
+    let action = match query_package_state(state, package_name, user) {
+        Ok(Some(pkg)) => RestoreAction::towards(pkg, target),
+        Ok(None) => return Err(AdbError::Other("package does not exist on this device".to_string())),
+        // guessing only helps when the device is there to run the guess
+        Err(e) if e.is_connection() => return Err(e),
+        Err(_) if hint == Some(&RemovalOutcome::Disabled) => RestoreAction::Enable,
+        Err(_) => RestoreAction::InstallExisting,
+    };
+
+    let user_arg = user.to_string();
+    for command in action.commands(package_name, &user_arg) {
+        if state.dry_run {
+            print_dry_run(state, &command);
+        } else {
+            state.invalidate_packages();
+            state.shell(&command)?;
+        }
     }
+
+    Ok(action)
 }
 
 fn restore_package(state: &mut AppState) {
     if !check_device_connected(state) {
-        println!("{}", "Error: No device connected!".red());
+        print_device_error(state);
         return;
     }
 
@@ -1312,26 +3436,35 @@ fn restore_package(state: &mut AppState) {
         return;
     }
 
-    println!("{} {}", "Restoring package:".yellow(), package_name.bright_white());
+    restore_package_by_name(state, &package_name);
+}
+
+fn restore_package_by_name(state: &AppState, package_name: &str) -> bool {
+    let users = state.users();
+    let mut all_ok = true;
 
-    let result = Command::new("adb")
-        .args(&["shell", "cmd", "package", "install-existing", package_name.as_str()])
-        .output();
+    for user in &users {
+        let user_note = if users.len() > 1 { format!(" (user {})", user) } else { String::new() };
+        println!("{} {}{}", "Restoring package:".yellow(), package_name.bright_white(), user_note);
 
-    match result {
-        Ok(output) if output.status.success() => {
-            println!("{} {}", "Successfully restored:".green().bold(), package_name);
-        }
-        _ => {
-            println!("{} {}", "Failed to restore:".red(), package_name);
-            println!("The package may not have been previously installed on this device.");
+        match restore_package_state(state, package_name, *user, PackageState::ACTIVE, None) {
+            Ok(_) if state.dry_run => {}
+            Ok(action) => {
+                println!("{} {} ({})", "Successfully restored:".green().bold(), package_name, action.describe());
+            }
+            Err(e) => {
+                println!("{} {} {}", "Failed to restore:".red(), package_name, e.to_string().bright_black());
+                println!("The package may not have been previously installed on this device.");
+                all_ok = false;
+            }
         }
     }
+    all_ok
 }
 
 fn search_packages(state: &mut AppState) {
     if !check_device_connected(state) {
-        println!("{}", "Error: No device connected!".red());
+        print_device_error(state);
         return;
     }
 
@@ -1341,23 +3474,33 @@ fn search_packages(state: &mut AppState) {
         return;
     }
 
+    find_packages(state, &search_term);
+}
+
+fn find_packages(state: &mut AppState, search_term: &str) -> bool {
+    if !check_device_connected(state) {
+        print_device_error(state);
+        return false;
+    }
+
     println!("{} '{}'...", "Searching for".yellow(), search_term.bright_white());
 
-    match execute_adb_command(&["shell", "pm", "list", "packages"]) {
-        Ok(output) => {
-            let mut found_packages = Vec::new();
-
-            for line in output.lines() {
-                if let Some(package_name) = line.strip_prefix("package:") {
-                    if package_name.to_lowercase().contains(&search_term.to_lowercase()) {
-                        found_packages.push(Package {
-                            name: package_name.to_string(),
-                            is_system: true,
-                            is_selected: false,
-                        });
-                    }
-                }
-            }
+    let mut installed = BTreeMap::new();
+    let listed = state.users().into_iter().try_for_each(|user| {
+        for package in state.user_packages(user)?.installed() {
+            installed.entry(package.name.clone()).or_insert_with(|| package.clone());
+        }
+        Ok::<(), AdbError>(())
+    });
+
+    match listed {
+        Ok(()) => {
+            // labels are searched too, so every package needs one
+            let mut packages: Vec<Package> = installed.into_values().collect();
+            label_packages(state, &mut packages);
+            let found_packages: Vec<Package> = packages.into_iter()
+                .filter(|p| p.matches(search_term))
+                .collect();
 
             if found_packages.is_empty() {
                 println!("{}", "No packages found matching search term.".yellow());
@@ -1366,51 +3509,50 @@ fn search_packages(state: &mut AppState) {
                     "Found".green().bold(), 
                     found_packages.len().to_string().bright_white()
                 );
-                display_packages(&found_packages, None);
+                display_packages(state, &found_packages, None);
             }
+            true
         }
         Err(e) => {
             println!("{} {}", "Error:".red(), e);
+            false
         }
     }
 }
 
-fn is_critical_package(package_name: &str) -> bool {
-    CRITICAL_PACKAGES.contains(&package_name)
-}
-
-fn create_backup(state: &AppState) {
+fn create_backup(state: &AppState) -> bool {
     if state.packages.is_empty() {
         println!("{}", "No packages loaded. Load packages first.".yellow());
-        return;
+        return false;
     }
 
-    let backup_path = get_temp_backup_path();
+    let backup_path = get_backup_path(state);
+    fs::create_dir_all(&state.backup_dir).ok();
 
-    let backup = Backup {
-        timestamp: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-        packages: state.packages.iter().map(|p| p.name.clone()).collect(),
-    };
+    let backup = Backup::capture(state, state.packages.iter().map(|p| p.name.clone()).collect());
 
     match serde_json::to_string_pretty(&backup) {
         Ok(json) => {
             if let Err(e) = fs::write(&backup_path, json) {
                 println!("{} {}", "Failed to create backup:".red(), e);
+                false
             } else {
                 println!("{} {}", "Backup created:".green().bold(), backup_path.display().to_string().bright_white());
-                show_temp_location();
+                show_backup_location(state);
+                true
             }
         }
         Err(e) => {
             println!("{} {}", "Failed to serialize backup:".red(), e);
+            false
         }
     }
 }
 
-fn show_device_info(state: &mut AppState) {
+fn show_device_info(state: &mut AppState) -> bool {
     if !check_device_connected(state) {
-        println!("{}", "Error: No device connected!".red());
-        return;
+        print_device_error(state);
+        return false;
     }
 
     println!();
@@ -1419,24 +3561,840 @@ fn show_device_info(state: &mut AppState) {
     println!("{}", "=========================================".cyan());
 
     let info_commands = vec![
-        ("Device Model", &["shell", "getprop", "ro.product.model"][..]),
-        ("Manufacturer", &["shell", "getprop", "ro.product.manufacturer"]),
-        ("Android Version", &["shell", "getprop", "ro.build.version.release"]),
-        ("SDK Version", &["shell", "getprop", "ro.build.version.sdk"]),
-        ("Serial Number", &["shell", "getprop", "ro.serialno"]),
+        ("Device Model", &["getprop", "ro.product.model"][..]),
+        ("Manufacturer", &["getprop", "ro.product.manufacturer"]),
+        ("Android Version", &["getprop", "ro.build.version.release"]),
+        ("SDK Version", &["getprop", "ro.build.version.sdk"]),
+        ("Serial Number", &["getprop", "ro.serialno"]),
     ];
 
     for (label, args) in info_commands {
-        match execute_adb_command(args) {
-            Ok(output) => {
-                let value = output.trim();
-                if !value.is_empty() {
-                    println!("{}: {}", label.cyan(), value.bright_white());
-                }
+        if let Ok(output) = state.shell(args) {
+            let value = output.trim();
+            if !value.is_empty() {
+                println!("{}: {}", label.cyan(), value.bright_white());
             }
-            Err(_) => {}
         }
     }
 
     println!("{}", "=========================================".cyan());
-}
\ No newline at end of file
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::{FakeDevice, FakePackage};
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("android_debloater_test_{}_{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn connected_state(device: &Arc<FakeDevice>, name: &str) -> AppState {
+        let mut state = AppState::with_backend(device.clone(), None, false);
+        state.backup_dir = test_dir(name);
+        state.label_dir = Some(test_dir(name).join("labels"));
+        state.snapshot_dir = Some(test_dir(name).join("snapshots"));
+        assert!(check_device_connected(&mut state));
+        state
+    }
+
+    #[test]
+    fn remove_uninstalls_package() {
+        let device = Arc::new(FakeDevice::new("fake1").with_system_package("com.facebook.katana"));
+        let state = connected_state(&device, "remove");
+
+        let result = remove_package(&state, "com.facebook.katana", 0);
+        assert_eq!(result.outcome, RemovalOutcome::Uninstalled);
+        assert!(!device.package("com.facebook.katana").unwrap().installed);
+    }
+
+    #[test]
+    fn remove_falls_back_to_disable() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.samsung.android.bixby.agent")
+            .fail_on("pm uninstall"));
+        let state = connected_state(&device, "disable");
+
+        let result = remove_package(&state, "com.samsung.android.bixby.agent", 0);
+        assert_eq!(result.outcome, RemovalOutcome::Disabled);
+        let pkg = device.package("com.samsung.android.bixby.agent").unwrap();
+        assert!(pkg.installed);
+        assert!(!pkg.enabled);
+    }
+
+    #[test]
+    fn remove_reports_failure() {
+        let device = Arc::new(FakeDevice::new("fake1").fail_on("pm"));
+        let state = connected_state(&device, "fail");
+
+        let result = remove_package(&state, "com.netflix.mediaclient", 0);
+        assert!(matches!(result.outcome, RemovalOutcome::Failed(_)));
+    }
+
+    #[test]
+    fn restore_reinstalls_package() {
+        let device = Arc::new(FakeDevice::new("fake1").with_system_package("com.android.egg"));
+        let state = connected_state(&device, "restore");
+
+        assert!(remove_package(&state, "com.android.egg", 0).outcome.is_success());
+        assert!(restore_package_by_name(&state, "com.android.egg"));
+        assert!(device.package("com.android.egg").unwrap().installed);
+    }
+
+    #[test]
+    fn restore_picks_action_from_package_state() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.egg")
+            .with_system_package("com.android.bips")
+            .with_system_package("com.android.dreams.basic")
+            .with_system_package("com.android.printspooler"));
+        let state = connected_state(&device, "restore_state");
+
+        state.shell(&["pm", "disable-user", "--user", "0", "com.android.egg"]).unwrap();
+        state.shell(&["pm", "uninstall", "--user", "0", "com.android.bips"]).unwrap();
+        state.shell(&["pm", "disable-user", "--user", "0", "com.android.dreams.basic"]).unwrap();
+        state.shell(&["pm", "uninstall", "--user", "0", "com.android.dreams.basic"]).unwrap();
+
+        let restore = |name| restore_package_state(&state, name, 0, PackageState::ACTIVE, None).unwrap();
+        assert_eq!(restore("com.android.egg"), RestoreAction::Enable);
+        assert_eq!(restore("com.android.bips"), RestoreAction::InstallExisting);
+        assert_eq!(restore("com.android.dreams.basic"), RestoreAction::InstallAndEnable);
+        assert_eq!(restore("com.android.printspooler"), RestoreAction::Nothing);
+        assert!(restore_package_state(&state, "com.not.there", 0, PackageState::ACTIVE, None).is_err());
+
+        for name in ["com.android.egg", "com.android.bips", "com.android.dreams.basic"] {
+            let pkg = device.package(name).unwrap();
+            assert!(pkg.installed && pkg.enabled, "{} not restored", name);
+        }
+    }
+
+    #[test]
+    fn batch_removal_writes_backup_that_restores() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.egg")
+            .with_system_package("com.android.bips"));
+        let mut state = connected_state(&device, "backup");
+        state.max_tier = Removal::Advanced;
+        let packages = vec!["com.android.egg".to_string(), "com.android.bips".to_string()];
+
+        assert!(remove_packages(&mut state, &packages, true));
+        assert!(!device.package("com.android.egg").unwrap().installed);
+        // one shell for the whole batch
+        assert_eq!(device.sessions(), 1);
+
+        let backups = find_backups(&state.backup_dir).unwrap();
+        assert_eq!(backups.len(), 1);
+        let backup: Backup = serde_json::from_str(&fs::read_to_string(&backups[0]).unwrap()).unwrap();
+        assert_eq!(backup.packages, packages);
+
+        assert!(restore_backup_file(&mut state, &backups[0], false));
+        assert!(device.package("com.android.egg").unwrap().installed);
+        assert!(device.package("com.android.bips").unwrap().installed);
+        assert_eq!(device.sessions(), 2);
+        assert!(state.session.borrow().is_none());
+
+        let _ = fs::remove_dir_all(&state.backup_dir);
+    }
+
+    #[test]
+    fn backup_records_outcomes_and_restore_reenables() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.egg")
+            .with_system_package("com.android.bips")
+            .fail_on("pm uninstall --user 0 com.android.bips"));
+        let mut state = connected_state(&device, "outcomes");
+        state.max_tier = Removal::Advanced;
+        let packages = vec![
+            "com.android.egg".to_string(),
+            "com.android.bips".to_string(),
+            "com.missing.app".to_string(),
+        ];
+
+        assert!(!remove_packages(&mut state, &packages, true));
+
+        let backups = find_backups(&state.backup_dir).unwrap();
+        let backup = read_backup(&backups[0]).unwrap();
+        assert_eq!(backup.users, [0]);
+        assert_eq!(backup.outcome(0, "com.android.egg"), Some(&RemovalOutcome::Uninstalled));
+        assert_eq!(backup.outcome(0, "com.android.bips"), Some(&RemovalOutcome::Disabled));
+        assert!(matches!(backup.outcome(0, "com.missing.app"), Some(RemovalOutcome::Failed(_))));
+
+        assert!(restore_backup_file(&mut state, &backups[0], false));
+        assert!(device.package("com.android.bips").unwrap().enabled);
+        let commands = device.commands();
+        assert!(commands.contains(&"pm enable --user 0 com.android.bips".to_string()));
+        assert!(!commands.iter().any(|c| c.starts_with("cmd package install-existing") && c.ends_with("com.android.bips")));
+        assert!(!commands.iter().any(|c| c.ends_with("com.missing.app") && !c.starts_with("pm uninstall") && !c.starts_with("pm disable")));
+
+        let _ = fs::remove_dir_all(&state.backup_dir);
+    }
+
+    #[test]
+    fn original_backups_still_load_and_restore() {
+        let dir = test_dir("original_backup");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("backup_20240101_100000.json");
+        fs::write(&path, r#"{"timestamp": "2024-01-01 10:00:00", "packages": ["com.android.egg", "com.android.bips"]}"#).unwrap();
+        let backup = read_backup(&path).unwrap();
+        assert_eq!(backup.version, 1);
+        assert_eq!(backup.users, [0]);
+        assert!(backup.device.is_none());
+        assert_eq!(backup.entry(0, "com.android.egg"), Some(&BackupEntry {
+            package: "com.android.egg".to_string(),
+            user: 0,
+            prior: None,
+            action: None,
+        }));
+        assert_eq!(backup.entries.len(), 2);
+
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.egg")
+            .with_system_package("com.android.bips"));
+        let mut state = connected_state(&device, "original_restore");
+        state.shell(&["pm", "uninstall", "--user", "0", "com.android.egg"]).unwrap();
+        state.shell(&["pm", "disable-user", "--user", "0", "com.android.bips"]).unwrap();
+        assert!(restore_backup_file(&mut state, &path, false));
+        assert_eq!(device.package("com.android.egg").unwrap(), FakePackage { system: true, installed: true, enabled: true });
+        assert!(device.package("com.android.bips").unwrap().enabled);
+
+        fs::write(&path, r#"{"version": 99, "timestamp": "2030-01-01 10:00:00", "packages": []}"#).unwrap();
+        assert!(read_backup(&path).is_err_and(|e| e.contains("newer")));
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_dir_all(&state.backup_dir);
+    }
+
+    #[test]
+    fn backup_records_device_and_prior_state() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_prop("ro.serialno", "R5CT1234")
+            .with_prop("ro.product.model", "Pixel 7")
+            .with_prop("ro.build.fingerprint", "google/panther/panther:14/UQ1A/1:user/release-keys")
+            .with_prop("ro.build.version.sdk", "34")
+            .with_system_package("com.android.egg")
+            .with_system_package("com.android.printspooler"));
+        let mut state = connected_state(&device, "identity");
+        let packages = vec!["com.android.egg".to_string(), "com.android.printspooler".to_string()];
+
+        // disabled by hand before the removal, so restoring leaves it disabled
+        state.shell(&["pm", "disable-user", "--user", "0", "com.android.printspooler"]).unwrap();
+        state.max_tier = Removal::Advanced;
+        assert!(remove_packages(&mut state, &packages, true));
+
+        let backups = find_backups(&state.backup_dir).unwrap();
+        let backup = read_backup(&backups[0]).unwrap();
+        assert_eq!(backup.version, BACKUP_VERSION);
+        let identity = backup.device.clone().unwrap();
+        assert_eq!(identity.serial, "R5CT1234");
+        assert_eq!(identity.model, "Pixel 7");
+        assert_eq!(identity.sdk, "34");
+        let entry = backup.entry(0, "com.android.printspooler").unwrap();
+        assert_eq!(entry.prior, Some(PackageState { installed: true, enabled: false }));
+        assert_eq!(entry.action, Some(RemovalOutcome::Uninstalled));
+
+        assert!(restore_backup_file(&mut state, &backups[0], false));
+        assert_eq!(device.package("com.android.egg").unwrap(), FakePackage { system: true, installed: true, enabled: true });
+        let spooler = device.package("com.android.printspooler").unwrap();
+        assert!(spooler.installed);
+        assert!(!spooler.enabled);
+
+        let other = DeviceIdentity {
+            serial: "R5CT9999".to_string(),
+            sdk: "33".to_string(),
+            ..identity.clone()
+        };
+        assert!(identity_mismatches(&identity, &identity).is_empty());
+        assert_eq!(identity_mismatches(&identity, &other).len(), 2);
+        // props that could not be read do not count as a mismatch
+        assert!(identity_mismatches(&identity, &DeviceIdentity::default()).is_empty());
+
+        let _ = fs::remove_dir_all(&state.backup_dir);
+    }
+
+    #[test]
+    fn listing_several_users_labels_once() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.egg")
+            .with_profile(10, "Work profile")
+            .with_system_package("com.facebook.katana"));
+        let mut state = connected_state(&device, "list_users");
+        state.users = vec![0, 10];
+        state.read_labels = true;
+
+        assert!(list_packages(&mut state, ListMode::System));
+        let names: Vec<&str> = state.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["com.android.egg", "com.facebook.katana"]);
+        let fingerprints = device.commands().iter().filter(|c| c.starts_with("getprop ro.build.fingerprint")).count();
+        assert_eq!(fingerprints, 1);
+    }
+
+    #[test]
+    fn removes_and_restores_per_user() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.egg")
+            .with_profile(10, "Work profile")
+            .with_system_package("com.facebook.katana"));
+        let mut state = connected_state(&device, "users");
+        state.all_users = true;
+        assert_eq!(state.users(), [0, 10]);
+
+        let packages = vec!["com.android.egg".to_string(), "com.facebook.katana".to_string()];
+        assert!(remove_packages(&mut state, &packages, true));
+        assert!(!device.package("com.android.egg").unwrap().installed);
+        assert!(!device.package_for(10, "com.android.egg").unwrap().installed);
+        assert!(!device.package("com.facebook.katana").unwrap().installed);
+        // the work profile never had it, so it is left alone
+        assert!(!device.commands().contains(&"pm uninstall --user 10 com.facebook.katana".to_string()));
+
+        let backups = find_backups(&state.backup_dir).unwrap();
+        let backup = read_backup(&backups[0]).unwrap();
+        assert_eq!(backup.users, [0, 10]);
+        assert_eq!(backup.outcome(10, "com.android.egg"), Some(&RemovalOutcome::Uninstalled));
+        assert_eq!(backup.outcome(10, "com.facebook.katana"), None);
+
+        state.all_users = false;
+        state.users = vec![0];
+        assert!(restore_backup_file(&mut state, &backups[0], false));
+        assert!(device.package_for(10, "com.android.egg").unwrap().installed);
+        assert!(device.package("com.facebook.katana").unwrap().installed);
+        assert!(!device.commands().iter().any(|c| c.contains("--user 10 com.facebook.katana") && !c.starts_with("pm list")));
+
+        let _ = fs::remove_dir_all(&state.backup_dir);
+    }
+
+    #[test]
+    fn pulled_apks_reinstall_packages_the_device_lost() {
+        let base = "/data/app/~~a==/com.spotify.music-b==/base.apk";
+        let split = "/data/app/~~a==/com.spotify.music-b==/split_config.arm64_v8a.apk";
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_user_package("com.spotify.music")
+            .with_apks("com.spotify.music", &[(base, b"base apk"), (split, b"split apk")]));
+        let mut state = connected_state(&device, "pull_apks");
+        state.pull_apks = true;
+        let packages = vec!["com.spotify.music".to_string()];
+
+        assert!(remove_packages(&mut state, &packages, true));
+        assert!(device.package("com.spotify.music").is_none());
+
+        let backups = find_backups(&state.backup_dir).unwrap();
+        let backup = read_backup(&backups[0]).unwrap();
+        let files = &backup.apks["com.spotify.music"];
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[1].file, "split_config.arm64_v8a.apk");
+        assert_eq!(files[0].sha256, apk_backup::sha256_hex(b"base apk"));
+
+        // a tampered apk is not installed
+        let dir = apk_dir(&backups[0], "com.spotify.music");
+        fs::write(dir.join("base.apk"), b"something else").unwrap();
+        assert!(!restore_backup_file(&mut state, &backups[0], false));
+        assert!(device.package("com.spotify.music").is_none());
+
+        fs::write(dir.join("base.apk"), b"base apk").unwrap();
+        assert!(restore_backup_file(&mut state, &backups[0], false));
+        assert!(device.package("com.spotify.music").unwrap().installed);
+        assert!(device.commands().iter().any(|c| c.starts_with("install-multiple --user 0") && c.ends_with("split_config.arm64_v8a.apk")));
+
+        let _ = fs::remove_dir_all(&state.backup_dir);
+    }
+
+    #[test]
+    fn snapshot_diff_catches_changes_on_the_device() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.egg")
+            .with_system_package("com.facebook.appmanager")
+            .with_profile(10, "Work profile"));
+        let mut state = connected_state(&device, "snapshot_diff");
+
+        assert!(take_snapshot(&mut state, None));
+        let path = snapshot::resolve(state.snapshot_dir.as_deref(), "latest").unwrap();
+        let saved = DeviceSnapshot::load(&path).unwrap();
+        assert_eq!(saved.users.keys().copied().collect::<Vec<_>>(), [0, 10]);
+        assert!(diff_snapshot(&mut state, &path, None));
+        assert!(diff_snapshot(&mut state, &path, Some(&path)));
+
+        // an update brings bloat back and the user disabled something in the profile
+        state.shell(&["pm", "uninstall", "--user", "0", "com.android.egg"]).unwrap();
+        state.shell(&["pm", "disable-user", "--user", "10", "com.facebook.appmanager"]).unwrap();
+        device.add_package("com.facebook.services", true);
+        state.invalidate_packages();
+
+        let live = capture_device_snapshot(&state).unwrap();
+        let changes: Vec<(u32, String, snapshot::Change)> = snapshot::diff(&saved, &live).into_iter()
+            .map(|d| (d.user, d.package, d.change))
+            .collect();
+        assert_eq!(changes, [
+            (0, "com.android.egg".to_string(), snapshot::Change::Removed),
+            (0, "com.facebook.services".to_string(), snapshot::Change::Appeared),
+            (10, "com.facebook.appmanager".to_string(), snapshot::Change::Disabled),
+        ]);
+        assert!(!diff_snapshot(&mut state, &path, None));
+
+        let _ = fs::remove_dir_all(test_dir("snapshot_diff"));
+    }
+
+    #[test]
+    fn revert_brings_the_device_back_to_a_snapshot() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.egg")
+            .with_system_package("com.facebook.appmanager")
+            .with_user_package("com.spotify.music"));
+        let mut state = connected_state(&device, "revert");
+
+        state.shell(&["pm", "disable-user", "--user", "0", "com.facebook.appmanager"]).unwrap();
+        state.shell(&["pm", "uninstall", "--user", "0", "com.android.egg"]).unwrap();
+        state.invalidate_packages();
+        let baseline = state.snapshot_dir.as_ref().unwrap().join("baseline.json");
+        assert!(take_snapshot(&mut state, Some(&baseline)));
+
+        // a test run messes the device up
+        state.shell(&["cmd", "package", "install-existing", "--user", "0", "com.android.egg"]).unwrap();
+        state.shell(&["pm", "enable", "--user", "0", "com.facebook.appmanager"]).unwrap();
+        state.shell(&["pm", "disable-user", "--user", "0", "com.spotify.music"]).unwrap();
+        device.add_package("com.facebook.katana", true);
+        state.invalidate_packages();
+        let before = device.commands().len();
+
+        assert!(revert_to_snapshot(&mut state, &baseline, false));
+        assert!(!device.package("com.android.egg").unwrap().installed);
+        assert!(!device.package("com.facebook.appmanager").unwrap().enabled);
+        assert!(device.package("com.spotify.music").unwrap().enabled);
+        assert!(!device.package("com.facebook.katana").unwrap().installed);
+        let changes = device.commands()[before..].iter().filter(|c| !c.starts_with("pm list") && !c.starts_with("getprop")).count();
+        assert_eq!(changes, 4);
+        // the steps went through one shell
+        assert_eq!(device.sessions(), 1);
+
+        // nothing left to do, and the state before the revert was kept
+        assert!(revert_to_snapshot(&mut state, &baseline, false));
+        assert_eq!(snapshot::find_snapshots(state.snapshot_dir.as_ref().unwrap()).len(), 2);
+
+        let _ = fs::remove_dir_all(test_dir("revert"));
+    }
+
+    #[test]
+    fn profile_applies_once_then_is_in_sync() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.facebook.appmanager")
+            .with_system_package("com.facebook.services")
+            .with_system_package("com.android.egg")
+            .with_user_package("com.spotify.music")
+            .with_profile(10, "Work profile"));
+        let mut state = connected_state(&device, "profile");
+        state.shell(&["pm", "disable-user", "--user", "0", "com.spotify.music"]).unwrap();
+
+        let profile_dir = test_dir("profile_file");
+        fs::create_dir_all(&profile_dir).unwrap();
+        let path = profile_dir.join("fleet.json");
+        fs::write(&path, r#"{
+            "name": "fleet",
+            "users": "all",
+            "packages": [
+                { "id": "com.facebook.*", "state": "removed" },
+                { "id": "com.android.egg", "state": "disabled" },
+                { "id": "com.spotify.music", "state": "enabled" },
+                { "id": "com.not.on.device", "state": "enabled" }
+            ]
+        }"#).unwrap();
+
+        assert!(apply_profile(&mut state, &path, true, false));
+        for user in [0, 10] {
+            assert!(!device.package_for(user, "com.facebook.appmanager").unwrap().installed);
+            assert!(!device.package_for(user, "com.facebook.services").unwrap().installed);
+            assert!(!device.package_for(user, "com.android.egg").unwrap().enabled);
+            assert!(device.package_for(user, "com.spotify.music").unwrap().enabled);
+        }
+        // the profile's users only applied for the run
+        assert_eq!(state.users(), [0]);
+        let backups = find_backups(&state.backup_dir).unwrap();
+        let backup = read_backup(&backups[0]).unwrap();
+        assert_eq!(backup.outcome(10, "com.android.egg"), Some(&RemovalOutcome::Disabled));
+
+        let before = device.commands().len();
+        assert!(apply_profile(&mut state, &path, true, false));
+        assert!(device.commands()[before..].iter().all(|c| c.starts_with("pm list") || c.starts_with("getprop")));
+        assert_eq!(find_backups(&state.backup_dir).unwrap().len(), 1);
+
+        // above the tier the whole run is refused
+        fs::write(&path, r#"{ "max_tier": "recommended", "packages": [ { "id": "com.android.bips", "state": "disabled" } ] }"#).unwrap();
+        device.add_package("com.android.bips", true);
+        state.invalidate_packages();
+        assert!(!apply_profile(&mut state, &path, true, false));
+        assert!(device.package("com.android.bips").unwrap().enabled);
+        // and a profile cannot raise --max-tier
+        fs::write(&path, r#"{ "max_tier": "unsafe", "packages": [ { "id": "com.android.bips", "state": "disabled" } ] }"#).unwrap();
+        assert!(!apply_profile(&mut state, &path, true, false));
+        assert!(device.package("com.android.bips").unwrap().enabled);
+        assert!(apply_profile(&mut state, &path, true, true));
+        assert!(!device.package("com.android.bips").unwrap().enabled);
+
+        let _ = fs::remove_dir_all(&state.backup_dir);
+        let _ = fs::remove_dir_all(&profile_dir);
+    }
+
+    #[test]
+    fn exported_profile_debloats_another_phone() {
+        let template = Arc::new(FakeDevice::new("template")
+            .with_prop("ro.product.model", "Pixel 7")
+            .with_system_package("com.facebook.appmanager")
+            .with_system_package("com.android.egg")
+            .with_system_package("com.custom.oem.thing")
+            .with_system_package("com.android.bips")
+            .with_user_package("com.spotify.music"));
+        let mut state = connected_state(&template, "export");
+        state.shell(&["pm", "disable-user", "--user", "0", "com.android.bips"]).unwrap();
+        state.shell(&["pm", "uninstall", "--user", "0", "com.facebook.appmanager"]).unwrap();
+        state.shell(&["pm", "disable-user", "--user", "0", "com.custom.oem.thing"]).unwrap();
+        // user apps that were uninstalled are gone from the listing, nothing to record
+        state.shell(&["pm", "uninstall", "--user", "0", "com.spotify.music"]).unwrap();
+
+        let path = test_dir("export_file").join("template.json");
+        assert!(export_profile(&mut state, &path));
+        let profile = Profile::load(&path).unwrap();
+        assert!(profile.name.starts_with("Pixel 7"));
+        assert_eq!(profile.users, None);
+        // the template's tier is not carried over, the fleet's --max-tier decides
+        assert_eq!(profile.max_tier, None);
+        let entries: Vec<(&str, Desired)> = profile.packages.iter().map(|e| (e.id.as_str(), e.state)).collect();
+        assert_eq!(entries, [
+            ("com.android.bips", Desired::Disabled),
+            ("com.custom.oem.thing", Desired::Disabled),
+            ("com.facebook.appmanager", Desired::Removed),
+        ]);
+        assert!(profile.packages[2].description.starts_with("Facebook App Manager"));
+        assert!(profile.packages[1].description.is_empty());
+
+        let phone = Arc::new(FakeDevice::new("phone")
+            .with_system_package("com.facebook.appmanager")
+            .with_system_package("com.custom.oem.thing"));
+        let mut state = connected_state(&phone, "export_apply");
+        assert!(apply_profile(&mut state, &path, false, false));
+        assert!(!phone.package("com.facebook.appmanager").unwrap().installed);
+        assert!(!phone.package("com.custom.oem.thing").unwrap().enabled);
+
+        let _ = fs::remove_dir_all(test_dir("export_file"));
+    }
+
+    #[test]
+    fn latest_backup_prefers_the_connected_device() {
+        let phone = Arc::new(FakeDevice::new("192.168.1.20:5555")
+            .with_prop("ro.serialno", "PHONE1")
+            .with_system_package("com.android.egg"));
+        let other = Arc::new(FakeDevice::new("emulator-5554")
+            .with_prop("ro.serialno", "OTHER")
+            .with_system_package("com.android.egg"));
+        let packages = vec!["com.android.egg".to_string()];
+
+        let mut state = connected_state(&phone, "latest");
+        assert!(remove_packages(&mut state, &packages, true));
+        let own = find_backups(&state.backup_dir).unwrap()[0].clone();
+        assert!(own.to_string_lossy().ends_with("_192.168.1.20_5555.json"));
+
+        let mut other_state = connected_state(&other, "latest_other");
+        other_state.backup_dir = state.backup_dir.clone();
+        assert!(remove_packages(&mut other_state, &packages, true));
+        assert_eq!(find_backups(&state.backup_dir).unwrap().len(), 2);
+
+        assert_eq!(latest_backup(&mut state), Ok(own));
+
+        // a third phone never gets either of them
+        let third = Arc::new(FakeDevice::new("R58M123ABC")
+            .with_prop("ro.serialno", "THIRD")
+            .with_system_package("com.android.egg"));
+        let mut third_state = connected_state(&third, "latest_third");
+        third_state.backup_dir = state.backup_dir.clone();
+        assert_eq!(latest_backup(&mut third_state), Err("No backup for this device (THIRD)".to_string()));
+        let _ = fs::remove_dir_all(&state.backup_dir);
+    }
+
+    #[test]
+    fn temp_dir_backups_move_to_the_data_dir() {
+        let old = test_dir("migrate_old");
+        let new = test_dir("migrate_new");
+        fs::create_dir_all(&old).unwrap();
+        fs::create_dir_all(&new).unwrap();
+        fs::write(old.join("backup_20240101_100000.json"), "{}").unwrap();
+        fs::write(old.join("backup_20240102_100000.json"), "{}").unwrap();
+        // already moved by an earlier run, the copy in the data dir wins
+        fs::write(new.join("backup_20240102_100000.json"), "[]").unwrap();
+
+        assert_eq!(migrate_legacy_backups(&old, &new), 1);
+        assert_eq!(find_backups(&new).unwrap().len(), 2);
+        assert_eq!(fs::read_to_string(new.join("backup_20240102_100000.json")).unwrap(), "[]");
+        assert_eq!(migrate_legacy_backups(&old, &new), 0);
+        assert_eq!(migrate_legacy_backups(&new, &new), 0);
+
+        let _ = fs::remove_dir_all(&old);
+        let _ = fs::remove_dir_all(&new);
+    }
+
+    #[test]
+    fn batch_removal_refuses_packages_above_max_tier() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.egg")
+            .with_system_package("com.android.bips")
+            .with_system_package("com.android.systemui"));
+        let mut state = connected_state(&device, "tiers");
+
+        // recommended is the default, advanced is one step too far
+        let packages = vec!["com.android.egg".to_string(), "com.android.bips".to_string()];
+        assert!(!remove_packages(&mut state, &packages, false));
+        assert!(device.commands().is_empty());
+
+        state.max_tier = Removal::Advanced;
+        assert!(remove_packages(&mut state, &packages, false));
+        assert!(!device.package("com.android.bips").unwrap().installed);
+
+        let packages = vec!["com.android.systemui".to_string()];
+        assert!(!remove_packages(&mut state, &packages, false));
+        state.max_tier = Removal::Unsafe;
+        assert!(remove_packages(&mut state, &packages, false));
+        assert!(!device.package("com.android.systemui").unwrap().installed);
+    }
+
+    #[test]
+    fn dry_run_leaves_device_and_backups_alone() {
+        let device = Arc::new(FakeDevice::new("fake1").with_system_package("com.android.egg"));
+        let mut state = connected_state(&device, "dry_run");
+        state.dry_run = true;
+        let packages = vec!["com.android.egg".to_string()];
+
+        assert!(remove_packages(&mut state, &packages, true));
+        assert!(restore_package_by_name(&state, "com.android.egg"));
+        assert!(device.package("com.android.egg").unwrap().installed);
+        assert!(device.commands().iter().all(|c| c.starts_with("pm list packages") || c.starts_with("getprop")));
+        assert!(!state.backup_dir.exists());
+        assert_eq!(
+            state.describe_shell(&["pm", "uninstall", "--user", "0", "com.android.egg"]),
+            "adb -s fake1 shell pm uninstall --user 0 com.android.egg"
+        );
+    }
+
+    #[test]
+    fn no_selected_user_is_an_error_not_a_panic() {
+        let device = Arc::new(FakeDevice::new("fake1").with_system_package("com.android.egg"));
+        let mut state = connected_state(&device, "no_users");
+        state.users = Vec::new();
+        assert!(state.installed_packages().is_err());
+    }
+
+    #[test]
+    fn pinned_serial_must_be_attached() {
+        let device = Arc::new(FakeDevice::new("fake1"));
+        let mut state = AppState::with_backend(device, Some("other".to_string()), false);
+
+        assert!(!check_device_connected(&mut state));
+        assert_eq!(state.connection, ConnectionType::None);
+        assert_eq!(state.device_error(), AdbError::DeviceNotFound("other".to_string()));
+    }
+
+    #[test]
+    fn device_errors_say_what_is_wrong() {
+        let device = Arc::new(FakeDevice::new("fake1").with_status("unauthorized"));
+        let mut state = AppState::with_backend(device, None, false);
+        assert!(!remove_packages(&mut state, &["com.facebook.katana".to_string()], false));
+        assert_eq!(state.device_error(), AdbError::Unauthorized("fake1".to_string()));
+        assert_eq!(state.device_error().exit_code(), 5);
+
+        // pm's reason comes through instead of its raw output
+        let device = Arc::new(FakeDevice::new("fake1"));
+        let state = connected_state(&device, "pm_reason");
+        let RemovalOutcome::Failed(reason) = remove_package(&state, "com.not.installed", 0).outcome else {
+            panic!("removing a missing package succeeded");
+        };
+        assert!(reason.starts_with("not installed for user 0 / "), "{}", reason);
+    }
+
+    #[test]
+    fn losing_the_device_midway_sets_the_exit_code() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.egg")
+            .fail_with("pm list packages", "error: device offline\n"));
+        let state = connected_state(&device, "offline");
+        let list = CliCommand::List { bloat: false, mode: ListMode::System };
+        assert_eq!(execute(state, list), 6);
+
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.egg")
+            .fail_with("pm list packages", "error: device unauthorized.\n"));
+        let state = connected_state(&device, "unauthorized");
+        assert_eq!(state.user_packages(0).err(), Some(AdbError::Unauthorized("fake1".to_string())));
+        assert_eq!(execute(state, CliCommand::Snapshot { output: None }), 5);
+
+        // a package pm refuses is a failed command, not a lost device
+        let device = Arc::new(FakeDevice::new("fake1").fail_with("pm disable-user", "Failure [DELETE_FAILED_USER_RESTRICTED]\n"));
+        let state = connected_state(&device, "pm_refused");
+        assert_eq!(disable_package(&state, "com.android.egg", 0), Err(AdbError::Package("DELETE_FAILED_USER_RESTRICTED".to_string())));
+        assert!(state.device_error.borrow().is_none());
+    }
+
+    #[test]
+    fn a_hung_command_stops_the_batch() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.egg")
+            .with_system_package("com.android.bips")
+            .hang_on("pm uninstall --user 0 com.android.bips"));
+        let mut state = connected_state(&device, "hung");
+        state.max_tier = Removal::Advanced;
+        let packages = vec!["com.android.bips".to_string(), "com.android.egg".to_string()];
+
+        assert!(!remove_packages(&mut state, &packages, false));
+        assert_eq!(state.device_error.borrow().clone(), Some(AdbError::Timeout("fake1".to_string())));
+        // nothing more was sent to the device once it stopped answering
+        assert_eq!(device.commands().last().unwrap(), "pm uninstall --user 0 com.android.bips");
+        assert!(device.package("com.android.egg").unwrap().installed);
+
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.bips")
+            .hang_on("pm uninstall"));
+        let state = connected_state(&device, "hung_exit");
+        let remove = CliCommand::Remove { packages: vec!["com.android.bips".to_string()], force: true, no_backup: true };
+        assert_eq!(execute(state, remove), 6);
+    }
+
+    #[test]
+    fn device_info_reads_props() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_prop("ro.product.model", "Pixel 7")
+            .with_user_package("com.spotify.music"));
+        let mut state = connected_state(&device, "info");
+
+        assert!(show_device_info(&mut state));
+        assert!(find_packages(&mut state, "spotify"));
+        assert!(device.commands().contains(&"getprop ro.product.model".to_string()));
+    }
+
+    #[test]
+    fn scan_finds_installed_bloatware() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.egg")
+            .with_system_package("com.android.systemui"));
+        let mut state = connected_state(&device, "scan");
+
+        scan_bloatware(&mut state).unwrap();
+        let names: Vec<_> = state.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["com.android.egg"]);
+    }
+
+    #[test]
+    fn package_listing_is_cached_and_matched_exactly() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.egg.extra")
+            .with_system_package("com.android.bips")
+            .with_user_package("com.facebook.katana"));
+        let mut state = connected_state(&device, "snapshot");
+
+        scan_bloatware(&mut state).unwrap();
+        let names: Vec<_> = state.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["com.android.bips", "com.facebook.katana"]);
+        assert!(state.packages[0].is_system && !state.packages[1].is_system);
+        let listings = || device.commands().iter().filter(|c| c.starts_with("pm list")).count();
+        assert_eq!(listings(), 4);
+
+        assert!(find_packages(&mut state, "EGG"));
+        fetch_packages(&mut state, ListMode::System).unwrap();
+        assert_eq!(listings(), 4);
+
+        remove_package(&state, "com.facebook.katana", 0);
+        scan_bloatware(&mut state).unwrap();
+        assert_eq!(state.packages.len(), 1);
+        assert_eq!(listings(), 8);
+    }
+
+    #[test]
+    fn labels_are_read_once_and_searchable() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.egg")
+            .with_system_package("com.android.bips")
+            .with_prop("ro.build.fingerprint", "google/raven/raven:14/UQ1A/1:user/release-keys")
+            .with_file("/system/app/com.android.egg/com.android.egg.apk", apk::testing::apk("Easter Egg")));
+        let mut state = connected_state(&device, "labels");
+
+        // without --read-labels the device is not asked for anything
+        let mut packages: Vec<Package> = state.installed_packages().unwrap().installed().cloned().collect();
+        let before = device.commands().len();
+        label_packages(&state, &mut packages);
+        assert_eq!(packages[1].label, None);
+        assert_eq!(device.commands().len(), before);
+        assert!(!test_dir("labels").join("labels").exists());
+
+        state.read_labels = true;
+        label_packages(&state, &mut packages);
+        assert_eq!(packages[1].name, "com.android.egg");
+        assert_eq!(packages[1].label.as_deref(), Some("Easter Egg"));
+        assert!(packages[1].matches("easter"));
+        // the print service apk could not be pulled, so it has no label yet
+        assert_eq!(packages[0].label, None);
+
+        let cached = LabelCache::load(state.label_dir.as_deref(), "google/raven/raven:14/UQ1A/1:user/release-keys");
+        assert_eq!(cached.get("com.android.egg"), Some("Easter Egg"));
+        assert_eq!(cached.get("com.android.bips"), None);
+
+        // and searched, from the cache this time
+        assert!(find_packages(&mut state, "Easter"));
+        let _ = fs::remove_dir_all(test_dir("labels"));
+    }
+
+    #[test]
+    fn listing_modes_split_by_state() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.egg")
+            .with_system_package("com.android.bips")
+            .with_system_package("com.android.printspooler")
+            .with_user_package("com.facebook.katana"));
+        let mut state = connected_state(&device, "modes");
+        state.shell(&["pm", "uninstall", "--user", "0", "com.android.egg"]).unwrap();
+        state.shell(&["pm", "disable-user", "--user", "0", "com.android.bips"]).unwrap();
+
+        let mut listed = |mode| {
+            fetch_packages(&mut state, mode).unwrap();
+            state.packages.iter().map(|p| p.name.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(listed(ListMode::System), ["com.android.bips", "com.android.printspooler"]);
+        assert_eq!(listed(ListMode::ThirdParty), ["com.facebook.katana"]);
+        assert_eq!(listed(ListMode::Disabled), ["com.android.bips"]);
+        assert_eq!(listed(ListMode::Enabled), ["com.android.printspooler", "com.facebook.katana"]);
+        assert_eq!(listed(ListMode::Uninstalled), ["com.android.egg"]);
+        assert_eq!(listed(ListMode::All).len(), 4);
+    }
+
+    #[test]
+    fn parses_package_listing_lines() {
+        let pkg = Package::parse_listing(
+            "package:/data/app/~~Yb3x==/com.facebook.katana-Qk1==/base.apk=com.facebook.katana versionCode:41234  installer=com.android.vending uid:10187"
+        ).unwrap();
+        assert_eq!(pkg.name, "com.facebook.katana");
+        assert_eq!(pkg.apk_path, "/data/app/~~Yb3x==/com.facebook.katana-Qk1==/base.apk");
+        assert_eq!(pkg.version_code, Some(41234));
+        assert_eq!(pkg.installer.as_deref(), Some("com.android.vending"));
+        assert_eq!(pkg.uid, Some(10187));
+
+        let pkg = Package::parse_listing("package:/system/app/Egg/Egg.apk=com.android.egg versionCode:34 installer=null uid:10045").unwrap();
+        assert_eq!(pkg.installer, None);
+        assert!(Package::parse_listing("package:com.android.egg").is_none());
+    }
+
+    #[test]
+    fn snapshot_records_package_state() {
+        let device = Arc::new(FakeDevice::new("fake1")
+            .with_system_package("com.android.egg")
+            .with_system_package("com.android.bips")
+            .with_user_package("com.facebook.katana"));
+        let state = connected_state(&device, "metadata");
+        state.shell(&["pm", "disable-user", "--user", "0", "com.android.bips"]).unwrap();
+        state.shell(&["pm", "uninstall", "--user", "0", "com.android.egg"]).unwrap();
+
+        let snapshot = state.installed_packages().unwrap();
+        let egg = &snapshot.packages["com.android.egg"];
+        assert!(egg.is_system && !egg.installed);
+        assert_eq!(egg.state_label(), Some("uninstalled"));
+        assert!(!snapshot.contains("com.android.egg"));
+        assert_eq!(snapshot.packages["com.android.bips"].state_label(), Some("disabled"));
+
+        let katana = &snapshot.packages["com.facebook.katana"];
+        assert!(!katana.is_system && katana.enabled);
+        assert_eq!(katana.installer.as_deref(), Some("com.android.vending"));
+        assert!(katana.apk_path.starts_with("/data/app/"));
+    }
+}
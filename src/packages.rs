@@ -0,0 +1,51 @@
+// how a package stands on the device, and which of them a listing shows
+
+use serde::{Deserialize, Serialize};
+
+// what the device currently says about a package for one user
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PackageState {
+    pub installed: bool,
+    pub enabled: bool,
+}
+
+impl PackageState {
+    pub const ACTIVE: PackageState = PackageState { installed: true, enabled: true };
+}
+
+// which packages `list` shows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ListMode {
+    System,
+    ThirdParty,
+    Disabled,
+    Enabled,
+    // removed for the user but still on the system partition
+    Uninstalled,
+    // everything, with a state column
+    All,
+}
+
+impl ListMode {
+    pub fn includes(self, state: PackageState, system: bool) -> bool {
+        match self {
+            ListMode::System => state.installed && system,
+            ListMode::ThirdParty => state.installed && !system,
+            ListMode::Disabled => state.installed && !state.enabled,
+            ListMode::Enabled => state.installed && state.enabled,
+            ListMode::Uninstalled => !state.installed,
+            ListMode::All => true,
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            ListMode::System => "system packages",
+            ListMode::ThirdParty => "user-installed packages",
+            ListMode::Disabled => "disabled packages",
+            ListMode::Enabled => "enabled packages",
+            ListMode::Uninstalled => "uninstalled packages",
+            ListMode::All => "packages",
+        }
+    }
+}
@@ -0,0 +1,77 @@
+// per-user directories for config and cache files
+
+use std::env;
+use std::path::PathBuf;
+
+const APP_DIR: &str = "android-debloater";
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .filter(|h| !h.is_empty())
+        .map(PathBuf::from)
+}
+
+// $XDG_CONFIG_HOME/android-debloater, %APPDATA%\android-debloater on windows
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir).join(APP_DIR));
+    }
+
+    match env::consts::OS {
+        "windows" => env::var_os("APPDATA").map(|d| PathBuf::from(d).join(APP_DIR)),
+        "macos" => home_dir().map(|h| h.join("Library").join("Application Support").join(APP_DIR)),
+        _ => home_dir().map(|h| h.join(".config").join(APP_DIR)),
+    }
+}
+
+// $XDG_CACHE_HOME/android-debloater, %LOCALAPPDATA%\android-debloater on windows
+pub fn cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_CACHE_HOME").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir).join(APP_DIR));
+    }
+
+    match env::consts::OS {
+        "windows" => env::var_os("LOCALAPPDATA").map(|d| PathBuf::from(d).join(APP_DIR)),
+        "macos" => home_dir().map(|h| h.join("Library").join("Caches").join(APP_DIR)),
+        _ => home_dir().map(|h| h.join(".cache").join(APP_DIR)),
+    }
+}
+
+// $XDG_DATA_HOME/android-debloater, %APPDATA%\android-debloater on windows
+pub fn data_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_DATA_HOME").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir).join(APP_DIR));
+    }
+
+    match env::consts::OS {
+        "windows" => env::var_os("APPDATA").map(|d| PathBuf::from(d).join(APP_DIR)),
+        "macos" => home_dir().map(|h| h.join("Library").join("Application Support").join(APP_DIR)),
+        _ => home_dir().map(|h| h.join(".local").join("share").join(APP_DIR)),
+    }
+}
+
+// removal backups, these have to survive reboots
+pub fn backups_dir() -> Option<PathBuf> {
+    data_dir().map(|d| d.join("backups"))
+}
+
+// full package state snapshots, see snapshot.rs
+pub fn snapshots_dir() -> Option<PathBuf> {
+    data_dir().map(|d| d.join("snapshots"))
+}
+
+// where backups were kept before they moved to the data dir
+pub fn legacy_backups_dir() -> PathBuf {
+    env::temp_dir().join("android_debloater_backups")
+}
+
+// app labels, one file per build fingerprint
+pub fn labels_dir() -> Option<PathBuf> {
+    cache_dir().map(|d| d.join("labels"))
+}
+
+// user supplied bloat lists are picked up from here
+pub fn lists_dir() -> Option<PathBuf> {
+    config_dir().map(|d| d.join("lists"))
+}
@@ -0,0 +1,178 @@
+// declarative debloat profiles: which packages should be removed, disabled or enabled,
+// for which users and up to which tier. `apply` only changes what differs
+
+use crate::bloatlist::Removal;
+use crate::packages::PackageState;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Desired {
+    Removed,
+    Disabled,
+    Enabled,
+}
+
+impl Desired {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Desired::Removed => "removed",
+            Desired::Disabled => "disabled",
+            Desired::Enabled => "enabled",
+        }
+    }
+
+    pub fn is_satisfied(self, state: Option<PackageState>) -> bool {
+        let state = state.unwrap_or(PackageState { installed: false, enabled: false });
+        match self {
+            Desired::Removed => !state.installed,
+            Desired::Disabled => state.installed && !state.enabled,
+            Desired::Enabled => state.installed && state.enabled,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProfileEntry {
+    // a package id, or a pattern with * and ? wildcards
+    pub id: String,
+    pub state: Desired,
+    // from the bloat list when exported, for whoever reads the file
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+}
+
+// "all", or a list of user ids
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ProfileUsers {
+    Ids(Vec<u32>),
+    Keyword(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(default)]
+    pub name: String,
+    // the users selected on the command line when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub users: Option<ProfileUsers>,
+    // riskiest tier the profile may remove or disable, can only lower --max-tier
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tier: Option<Removal>,
+    pub packages: Vec<ProfileEntry>,
+}
+
+impl Profile {
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize profile: {}", e))?;
+        fs::write(path, json).map_err(|e| format!("Failed to write profile: {}", e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read profile {}: {}", path.display(), e))?;
+        let profile: Profile = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse profile {}: {}", path.display(), e))?;
+        if let Some(ProfileUsers::Keyword(word)) = &profile.users {
+            if word != "all" {
+                return Err(format!("{}: users must be a list of ids or \"all\", not \"{}\"", path.display(), word));
+            }
+        }
+        Ok(profile)
+    }
+
+    // what the profile wants for each package the device knows, later entries win.
+    // plain ids are kept even when the device does not have them
+    pub fn resolve<'a>(&self, known: impl Iterator<Item = &'a String> + Clone) -> BTreeMap<String, Desired> {
+        let mut wanted = BTreeMap::new();
+        for entry in &self.packages {
+            if is_pattern(&entry.id) {
+                for name in known.clone().filter(|n| glob_match(&entry.id, n)) {
+                    wanted.insert(name.clone(), entry.state);
+                }
+            } else {
+                wanted.insert(entry.id.clone(), entry.state);
+            }
+        }
+        wanted
+    }
+}
+
+fn is_pattern(id: &str) -> bool {
+    id.contains(['*', '?'])
+}
+
+// * matches any run of characters, ? exactly one
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // where the last * was and the text position it is currently standing for
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_match_package_ids() {
+        assert!(glob_match("com.facebook.*", "com.facebook.katana"));
+        assert!(glob_match("*.samsung.*bixby*", "com.samsung.android.bixby.agent"));
+        assert!(glob_match("com.android.eg?", "com.android.egg"));
+        assert!(!glob_match("com.facebook.*", "com.facebookx.katana"));
+        assert!(!glob_match("com.android.eg?", "com.android.eggs"));
+        assert!(glob_match("*", ""));
+    }
+
+    #[test]
+    fn later_entries_override_patterns() {
+        let profile: Profile = serde_json::from_str(r#"{
+            "name": "fleet",
+            "users": "all",
+            "max_tier": "advanced",
+            "packages": [
+                { "id": "com.facebook.*", "state": "removed" },
+                { "id": "com.facebook.katana", "state": "disabled" },
+                { "id": "com.not.on.device", "state": "enabled" }
+            ]
+        }"#).unwrap();
+        assert_eq!(profile.users, Some(ProfileUsers::Keyword("all".to_string())));
+        assert_eq!(profile.max_tier, Some(Removal::Advanced));
+
+        let known = ["com.facebook.katana".to_string(), "com.facebook.services".to_string(), "com.android.egg".to_string()];
+        let wanted = profile.resolve(known.iter());
+        assert_eq!(wanted.len(), 3);
+        assert_eq!(wanted["com.facebook.services"], Desired::Removed);
+        assert_eq!(wanted["com.facebook.katana"], Desired::Disabled);
+        assert_eq!(wanted["com.not.on.device"], Desired::Enabled);
+
+        assert!(Desired::Removed.is_satisfied(None));
+        assert!(!Desired::Disabled.is_satisfied(Some(PackageState { installed: false, enabled: false })));
+    }
+}
@@ -0,0 +1,175 @@
+// one long-lived shell on the device for a batch of commands. each command is followed by
+// an echo of a marker and its exit code, so results can be split apart in the output stream
+
+use std::io::{self, BufRead, Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+// how long a command may run without printing anything before the device counts as hung
+pub const COMMAND_TIMEOUT: Duration = Duration::from_secs(600);
+
+pub trait ShellSession {
+    // the command's output and exit code, Err when the session itself broke
+    fn run(&mut self, args: &[&str]) -> io::Result<(String, i32)>;
+}
+
+pub struct ShellStream<R: BufRead, W: Write> {
+    reader: R,
+    writer: W,
+    marker: String,
+    count: u64,
+}
+
+impl<R: BufRead, W: Write> ShellStream<R, W> {
+    pub fn new(reader: R, writer: W) -> Self {
+        ShellStream {
+            reader,
+            writer,
+            marker: format!("__android_debloater_{}_", std::process::id()),
+            count: 0,
+        }
+    }
+
+    // asks the shell to exit, the session is unusable afterwards
+    pub fn close(&mut self) {
+        let _ = self.writer.write_all(b"exit\n");
+        let _ = self.writer.flush();
+    }
+}
+
+impl<R: BufRead, W: Write> ShellSession for ShellStream<R, W> {
+    fn run(&mut self, args: &[&str]) -> io::Result<(String, i32)> {
+        self.count += 1;
+        // the number keeps a late marker from an earlier command from ending this one
+        let marker = format!("{}{}:", self.marker, self.count);
+        // stdin is the session itself, a command reading it would swallow the next ones
+        writeln!(self.writer, "{} 2>&1 </dev/null; echo \"{}$?\"", args.join(" "), marker)?;
+        self.writer.flush()?;
+
+        let mut output = String::new();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "device shell closed"));
+            }
+            // output without a trailing newline ends up on the marker's line
+            if let Some(at) = line.find(&marker) {
+                output.push_str(&line[..at]);
+                let code = line[at + marker.len()..].trim().parse()
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("bad exit code in {:?}", line)))?;
+                return Ok((output, code));
+            }
+            output.push_str(&line);
+        }
+    }
+}
+
+// reads a pipe on its own thread, so a read can give up after `timeout` with TimedOut
+// where a pipe read would wait forever
+pub struct TimedReader {
+    chunks: Receiver<Vec<u8>>,
+    buffer: Vec<u8>,
+    pos: usize,
+    timeout: Duration,
+}
+
+impl TimedReader {
+    pub fn new<R: Read + Send + 'static>(mut source: R, timeout: Duration) -> Self {
+        let (sender, chunks) = mpsc::channel();
+        thread::spawn(move || {
+            let mut chunk = [0; 8192];
+            // ends at eof, on an error or once the reader is dropped
+            while let Ok(n @ 1..) = source.read(&mut chunk) {
+                if sender.send(chunk[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+        TimedReader { chunks, buffer: Vec::new(), pos: 0, timeout }
+    }
+}
+
+impl Read for TimedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for TimedReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.buffer.len() {
+            match self.chunks.recv_timeout(self.timeout) {
+                Ok(chunk) => {
+                    self.buffer = chunk;
+                    self.pos = 0;
+                }
+                Err(RecvTimeoutError::Timeout) => return Err(io::Error::new(io::ErrorKind::TimedOut, "no answer from the device")),
+                // the pipe closed, an empty buffer is eof
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.buffer.clear();
+                    self.pos = 0;
+                }
+            }
+        }
+        Ok(&self.buffer[self.pos..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pos = (self.pos + amount).min(self.buffer.len());
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+    use std::process::{Command, Stdio};
+
+    #[test]
+    fn splits_results_of_a_local_shell() {
+        let mut child = Command::new("sh")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut session = ShellStream::new(stdout, stdin);
+
+        assert_eq!(session.run(&["echo", "package:com.android.egg"]).unwrap(), ("package:com.android.egg\n".to_string(), 0));
+        assert_eq!(session.run(&["printf", "Failure", ";", "false"]).unwrap(), ("Failure".to_string(), 1));
+        // stderr comes along, and a command waiting on stdin does not eat the next one
+        let (output, code) = session.run(&["ls", "/no/such/dir"]).unwrap();
+        assert!(output.contains("/no/such/dir"));
+        assert_ne!(code, 0);
+        assert_eq!(session.run(&["cat"]).unwrap(), (String::new(), 0));
+        assert_eq!(session.run(&["exit", "3"]).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        session.close();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn a_silent_command_times_out() {
+        let mut child = Command::new("sh")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = TimedReader::new(child.stdout.take().unwrap(), Duration::from_millis(200));
+        let mut session = ShellStream::new(stdout, stdin);
+
+        assert_eq!(session.run(&["echo", "ok"]).unwrap(), ("ok\n".to_string(), 0));
+        assert_eq!(session.run(&["sleep", "5"]).unwrap_err().kind(), io::ErrorKind::TimedOut);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
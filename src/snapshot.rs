@@ -0,0 +1,327 @@
+// the state of every package for every user, saved to compare against later
+
+use crate::device::DeviceIdentity;
+use crate::packages::PackageState;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+// snapshots are the baseline a revert goes back to, so they are never put in the temp dir
+pub const NO_SNAPSHOT_DIR: &str = "no data directory to keep snapshots in, pass --snapshot-dir or set ANDROID_DEBLOATER_SNAPSHOT_DIR";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeviceSnapshot {
+    pub version: u32,
+    pub timestamp: String,
+    pub device: DeviceIdentity,
+    // user id -> every package that user's pm knows about, uninstalled ones included
+    pub users: BTreeMap<u32, BTreeMap<String, PackageState>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    Removed,
+    Disabled,
+    Enabled,
+    Appeared,
+}
+
+impl Change {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Change::Removed => "removed",
+            Change::Disabled => "disabled",
+            Change::Enabled => "re-enabled",
+            Change::Appeared => "appeared",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difference {
+    pub user: u32,
+    pub package: String,
+    pub change: Change,
+}
+
+impl DeviceSnapshot {
+    pub fn new(device: DeviceIdentity) -> Self {
+        DeviceSnapshot {
+            version: SNAPSHOT_VERSION,
+            timestamp: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            device,
+            users: BTreeMap::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read snapshot {}: {}", path.display(), e))?;
+        let snapshot: DeviceSnapshot = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse snapshot {}: {}", path.display(), e))?;
+        if snapshot.version > SNAPSHOT_VERSION {
+            return Err(format!("Snapshot format version {} is newer than this tool supports ({})", snapshot.version, SNAPSHOT_VERSION));
+        }
+        Ok(snapshot)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize snapshot: {}", e))?;
+        fs::write(path, json).map_err(|e| format!("Failed to write snapshot: {}", e))
+    }
+
+    // users only one of the two snapshots has
+    pub fn unmatched_users(&self, other: &DeviceSnapshot) -> Vec<u32> {
+        let ours: BTreeSet<u32> = self.users.keys().copied().collect();
+        let theirs: BTreeSet<u32> = other.users.keys().copied().collect();
+        ours.symmetric_difference(&theirs).copied().collect()
+    }
+}
+
+// how `new` differs from `old`, for the users both have. a package missing from a
+// snapshot counts as not installed
+pub fn diff(old: &DeviceSnapshot, new: &DeviceSnapshot) -> Vec<Difference> {
+    let missing = PackageState { installed: false, enabled: false };
+    let mut differences = Vec::new();
+
+    for (user, old_packages) in &old.users {
+        let Some(new_packages) = new.users.get(user) else { continue };
+        let names: BTreeSet<&String> = old_packages.keys().chain(new_packages.keys()).collect();
+
+        for name in names {
+            let before = old_packages.get(name).copied().unwrap_or(missing);
+            let after = new_packages.get(name).copied().unwrap_or(missing);
+
+            let change = match (before.installed, after.installed) {
+                (true, false) => Some(Change::Removed),
+                (false, true) => Some(Change::Appeared),
+                (true, true) if before.enabled && !after.enabled => Some(Change::Disabled),
+                (true, true) if !before.enabled && after.enabled => Some(Change::Enabled),
+                _ => None,
+            };
+            if let Some(change) = change {
+                differences.push(Difference { user: *user, package: name.clone(), change });
+            }
+        }
+    }
+    differences
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Uninstall,
+    Disable,
+    Enable,
+    InstallExisting,
+}
+
+impl Operation {
+    pub fn command<'a>(self, package: &'a str, user: &'a str) -> Vec<&'a str> {
+        match self {
+            Operation::Uninstall => vec!["pm", "uninstall", "--user", user, package],
+            Operation::Disable => vec!["pm", "disable-user", "--user", user, package],
+            Operation::Enable => vec!["pm", "enable", "--user", user, package],
+            Operation::InstallExisting => vec!["cmd", "package", "install-existing", "--user", user, package],
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Operation::Uninstall => "uninstall",
+            Operation::Disable => "disable",
+            Operation::Enable => "enable",
+            Operation::InstallExisting => "reinstall",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub user: u32,
+    pub package: String,
+    pub operation: Operation,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Plan {
+    // in the order they have to run
+    pub steps: Vec<Step>,
+    // installed in the target but the device no longer has an apk for them
+    pub unavailable: Vec<(u32, String)>,
+}
+
+// the fewest operations that bring `current` to `target`, for the users both have.
+// packages the target does not know about are uninstalled
+pub fn plan(target: &DeviceSnapshot, current: &DeviceSnapshot) -> Plan {
+    let mut plan = Plan::default();
+
+    for (user, wanted) in &target.users {
+        let Some(present) = current.users.get(user) else { continue };
+        let names: BTreeSet<&String> = wanted.keys().chain(present.keys()).collect();
+
+        for name in names {
+            let mut step = |operation| plan.steps.push(Step { user: *user, package: name.clone(), operation });
+            let want = wanted.get(name).copied().unwrap_or(PackageState { installed: false, enabled: true });
+            let Some(have) = present.get(name).copied() else {
+                if want.installed {
+                    plan.unavailable.push((*user, name.clone()));
+                }
+                continue;
+            };
+
+            match (want.installed, have.installed) {
+                (false, true) => step(Operation::Uninstall),
+                (false, false) => {}
+                (true, installed) => {
+                    if !installed {
+                        step(Operation::InstallExisting);
+                    }
+                    if want.enabled && !have.enabled {
+                        step(Operation::Enable);
+                    } else if !want.enabled && have.enabled {
+                        step(Operation::Disable);
+                    }
+                }
+            }
+        }
+    }
+    plan
+}
+
+// a new file name in `dir`, two snapshots taken in the same second get a suffix
+pub fn snapshot_path(dir: &Path) -> PathBuf {
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+    let mut path = dir.join(format!("snapshot_{}.json", timestamp));
+    let mut n = 2;
+    while path.exists() {
+        path = dir.join(format!("snapshot_{}_{}.json", timestamp, n));
+        n += 1;
+    }
+    path
+}
+
+// snapshot files in `dir`, newest first
+pub fn find_snapshots(dir: &Path) -> Vec<PathBuf> {
+    let mut snapshots: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|e| e == "json"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    snapshots.sort();
+    snapshots.reverse();
+    snapshots
+}
+
+// "latest", a path, or the name of a file in `dir`. only a path works without a dir
+pub fn resolve(dir: Option<&Path>, name: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(name);
+    if name != "latest" && path.exists() {
+        return Ok(path);
+    }
+    let dir = dir.ok_or_else(|| format!("Snapshot not found: {} ({})", name, NO_SNAPSHOT_DIR))?;
+    if name == "latest" {
+        return find_snapshots(dir).into_iter().next()
+            .ok_or_else(|| format!("No snapshots found in {}", dir.display()));
+    }
+    let in_dir = dir.join(name);
+    if in_dir.exists() {
+        return Ok(in_dir);
+    }
+    Err(format!("Snapshot not found: {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(installed: bool, enabled: bool) -> PackageState {
+        PackageState { installed, enabled }
+    }
+
+    #[test]
+    fn diff_reports_each_kind_of_change() {
+        let mut old = DeviceSnapshot::new(DeviceIdentity::default());
+        let mut new = old.clone();
+
+        old.users.insert(0, BTreeMap::from([
+            ("com.removed".to_string(), state(true, true)),
+            ("com.disabled".to_string(), state(true, true)),
+            ("com.enabled".to_string(), state(true, false)),
+            ("com.came.back".to_string(), state(false, true)),
+            ("com.same".to_string(), state(true, true)),
+        ]));
+        new.users.insert(0, BTreeMap::from([
+            ("com.disabled".to_string(), state(true, false)),
+            ("com.enabled".to_string(), state(true, true)),
+            ("com.came.back".to_string(), state(true, true)),
+            ("com.same".to_string(), state(true, true)),
+            ("com.new".to_string(), state(true, true)),
+        ]));
+        new.users.insert(10, BTreeMap::new());
+
+        let differences = diff(&old, &new);
+        let find = |name: &str| differences.iter().find(|d| d.package == name).map(|d| d.change);
+        assert_eq!(differences.len(), 5);
+        assert_eq!(find("com.removed"), Some(Change::Removed));
+        assert_eq!(find("com.disabled"), Some(Change::Disabled));
+        assert_eq!(find("com.enabled"), Some(Change::Enabled));
+        assert_eq!(find("com.came.back"), Some(Change::Appeared));
+        assert_eq!(find("com.new"), Some(Change::Appeared));
+        assert_eq!(find("com.same"), None);
+        assert_eq!(old.unmatched_users(&new), [10]);
+    }
+
+    #[test]
+    fn plan_takes_the_fewest_steps_back() {
+        let mut target = DeviceSnapshot::new(DeviceIdentity::default());
+        let mut current = target.clone();
+
+        target.users.insert(0, BTreeMap::from([
+            ("com.kept".to_string(), state(true, true)),
+            ("com.to.reinstall".to_string(), state(true, true)),
+            ("com.to.disable".to_string(), state(true, false)),
+            ("com.reinstall.disabled".to_string(), state(true, false)),
+            ("com.was.removed".to_string(), state(false, true)),
+            ("com.gone".to_string(), state(true, true)),
+        ]));
+        current.users.insert(0, BTreeMap::from([
+            ("com.kept".to_string(), state(true, true)),
+            ("com.to.reinstall".to_string(), state(false, true)),
+            ("com.to.disable".to_string(), state(true, true)),
+            ("com.reinstall.disabled".to_string(), state(false, true)),
+            ("com.was.removed".to_string(), state(true, true)),
+            ("com.new.bloat".to_string(), state(true, true)),
+        ]));
+
+        let plan = plan(&target, &current);
+        let steps: Vec<(&str, Operation)> = plan.steps.iter().map(|s| (s.package.as_str(), s.operation)).collect();
+        assert_eq!(steps, [
+            ("com.new.bloat", Operation::Uninstall),
+            ("com.reinstall.disabled", Operation::InstallExisting),
+            ("com.reinstall.disabled", Operation::Disable),
+            ("com.to.disable", Operation::Disable),
+            ("com.to.reinstall", Operation::InstallExisting),
+            ("com.was.removed", Operation::Uninstall),
+        ]);
+        assert_eq!(plan.unavailable, [(0, "com.gone".to_string())]);
+        assert_eq!(Operation::Disable.command("com.a", "10"), ["pm", "disable-user", "--user", "10", "com.a"]);
+    }
+
+    #[test]
+    fn resolve_without_a_dir_only_takes_paths() {
+        let err = resolve(None, "latest").unwrap_err();
+        assert!(err.contains("--snapshot-dir"), "{}", err);
+        assert!(resolve(None, "baseline.json").is_err());
+        assert_eq!(resolve(None, "Cargo.toml").unwrap(), PathBuf::from("Cargo.toml"));
+    }
+}
@@ -0,0 +1,129 @@
+// importer for Universal Android Debloater's uad_lists.json
+
+use crate::bloatlist::{BloatEntry, Removal};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UadPackage {
+    // missing in the newer map-keyed layout, filled from the key
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    list: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    dependencies: Option<Vec<String>>,
+    #[serde(default)]
+    needed_by: Option<Vec<String>>,
+    #[serde(default)]
+    labels: Option<Vec<String>>,
+    #[serde(default)]
+    removal: String,
+}
+
+// uad_lists.json has shipped both as an array and as an object keyed by package id
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum UadFile {
+    List(Vec<UadPackage>),
+    Map(BTreeMap<String, UadPackage>),
+}
+
+// "Unlisted" and anything newer: be careful
+fn parse_removal(removal: &str) -> Removal {
+    Removal::parse(removal).unwrap_or(Removal::Expert)
+}
+
+pub fn parse_uad(content: &str) -> Result<Vec<BloatEntry>, String> {
+    let file: UadFile = serde_json::from_str(content)
+        .map_err(|e| format!("not a UAD list: {}", e))?;
+
+    let packages: Vec<UadPackage> = match file {
+        UadFile::List(packages) => packages,
+        UadFile::Map(map) => map.into_iter()
+            .map(|(id, mut pkg)| {
+                pkg.id = id;
+                pkg
+            })
+            .collect(),
+    };
+
+    Ok(packages.into_iter()
+        .filter(|p| !p.id.is_empty())
+        .map(|p| BloatEntry {
+            removal: parse_removal(&p.removal),
+            id: p.id,
+            // UAD descriptions are often several paragraphs, keep them whole
+            description: p.description.trim().to_string(),
+            vendor: p.list,
+            notes: String::new(),
+            dependencies: p.dependencies.unwrap_or_default(),
+            needed_by: p.needed_by.unwrap_or_default(),
+            labels: p.labels.unwrap_or_default(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_array_layout() {
+        let entries = parse_uad(r#"[
+            {
+                "id": "com.samsung.android.bixby.agent",
+                "list": "Oem",
+                "description": "Bixby voice assistant\n",
+                "dependencies": [],
+                "neededBy": ["com.samsung.android.bixby.wakeup"],
+                "labels": [],
+                "removal": "Recommended"
+            },
+            {
+                "id": "com.android.systemui",
+                "list": "Aosp",
+                "description": "System UI",
+                "dependencies": null,
+                "neededBy": null,
+                "labels": null,
+                "removal": "Unsafe"
+            }
+        ]"#).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].vendor, "Oem");
+        assert_eq!(entries[0].description, "Bixby voice assistant");
+        assert_eq!(entries[0].removal, Removal::Recommended);
+        assert_eq!(entries[0].needed_by, ["com.samsung.android.bixby.wakeup"]);
+        assert_eq!(entries[1].removal, Removal::Unsafe);
+        assert!(entries[1].dependencies.is_empty());
+    }
+
+    #[test]
+    fn parses_map_layout() {
+        let entries = parse_uad(r#"{
+            "com.google.android.youtube": {
+                "list": "Google",
+                "description": "YouTube",
+                "dependencies": [],
+                "neededBy": [],
+                "labels": [],
+                "removal": "Recommended"
+            },
+            "com.oem.thing": { "list": "Pending", "description": "", "removal": "Unlisted" }
+        }"#).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].id, "com.google.android.youtube");
+        assert_eq!(entries[1].removal, Removal::Expert);
+    }
+
+    #[test]
+    fn rejects_other_json() {
+        assert!(parse_uad(r#"{"packages": 3}"#).is_err());
+    }
+}
@@ -0,0 +1,59 @@
+// android users and profiles, as listed by `pm list users`
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceUser {
+    pub id: u32,
+    // "Owner", "Work profile", "Guest", ...
+    pub name: String,
+    pub running: bool,
+}
+
+// Users:
+//     UserInfo{0:Owner:c13} running
+//     UserInfo{10:Work profile:1030} running
+pub fn parse_users(output: &str) -> Vec<DeviceUser> {
+    let mut users = Vec::new();
+
+    for line in output.lines() {
+        let info = match line.split_once("UserInfo{").and_then(|(_, rest)| rest.split_once('}')) {
+            Some((info, rest)) => (info, rest),
+            None => continue,
+        };
+
+        // id:name:flags, the name itself may contain colons
+        let (id, rest) = match info.0.split_once(':') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let name = rest.rsplit_once(':').map(|(name, _)| name).unwrap_or(rest);
+
+        if let Ok(id) = id.parse() {
+            users.push(DeviceUser {
+                id,
+                name: name.to_string(),
+                running: info.1.contains("running"),
+            });
+        }
+    }
+
+    users
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_users_and_profiles() {
+        let users = parse_users("Users:\n\
+            \tUserInfo{0:Owner:c13} running\n\
+            \tUserInfo{10:Work profile:1030} running\n\
+            \tUserInfo{11:Guest: 2:414}\n");
+
+        assert_eq!(users.len(), 3);
+        assert_eq!(users[0], DeviceUser { id: 0, name: "Owner".to_string(), running: true });
+        assert_eq!(users[1].name, "Work profile");
+        assert_eq!(users[2].name, "Guest: 2");
+        assert!(!users[2].running);
+    }
+}